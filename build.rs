@@ -1,5 +1,26 @@
 extern crate lalrpop;
 
+use std::env;
+use std::path::Path;
+use std::process::Command;
+
 fn main() {
+    // Listing any file below turns off cargo's rerun on every change, so the
+    // grammar has to be listed too.
+    println!("cargo:rerun-if-changed=src/grammar.lalrpop");
     lalrpop::process_root().unwrap();
+    compile_runtime();
+}
+
+/// Compiles the C runtime once, into an object that `emit::link` links into
+/// every executable.
+fn compile_runtime() {
+    println!("cargo:rerun-if-changed=runtime/runtime.c");
+    let object = Path::new(&env::var("OUT_DIR").unwrap()).join("runtime.o");
+    let status = Command::new("cc")
+        .args(["-c", "-O2", "-fPIC", "runtime/runtime.c", "-o"])
+        .arg(&object)
+        .status()
+        .expect("could not run `cc` to compile the runtime");
+    assert!(status.success(), "compiling the runtime with `cc` failed");
 }
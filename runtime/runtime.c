@@ -0,0 +1,27 @@
+/* The runtime linked into every executable: the built-in functions of the
+ * language, as declared in src/runtime.rs. */
+#include <stdio.h>
+#include <stdlib.h>
+
+/* Reads one integer from standard input. */
+int input(void) {
+    int value;
+    if (scanf("%d", &value) != 1) {
+        fprintf(stderr, "input: expected an integer\n");
+        exit(1);
+    }
+    return value;
+}
+
+/* Writes an integer and a newline to standard output. */
+void output(int x) {
+    printf("%d\n", x);
+}
+
+/* Called by the code --bounds-check adds when an index is out of range. */
+void __bounds_check_failed(const char *location, const char *array, int index, int length) {
+    fflush(stdout);
+    fprintf(stderr, "%s: index %d out of bounds for array `%s` of length %d\n",
+            location, index, array, length);
+    exit(1);
+}
@@ -1,4 +1,5 @@
 use std::fmt::{Debug};
+use crate::span::Span;
 
 #[derive(PartialEq, Debug)]
 pub struct Program {
@@ -12,12 +13,13 @@ pub struct FunctionDeclaration {
     pub function_name: String,
     pub params: Vec<Param>,
     pub body: Box<CompoundStatement>,
+    pub span: Span,
 }
 
 #[derive(PartialEq, Debug)]
 pub enum Param {
-    Var(IdentifierType, String),
-    ArrVar(IdentifierType, String)
+    Var(IdentifierType, String, Span),
+    ArrVar(IdentifierType, String, Span)
 }
 
 #[derive(PartialEq, Debug, Copy, Clone)]
@@ -26,53 +28,58 @@ pub enum IdentifierType {
     Void
 }
 
+#[allow(clippy::enum_variant_names)]
 #[derive(PartialEq, Debug)]
 pub enum Statement {
     Expression(Box<Expression>),
     CompoundStatement(Box<CompoundStatement>),
     IfStatement(Box<IfStatement>),
     WhileStatement(Box<WhileStatement>),
-    ReturnStatement(Option<Box<Expression>>),
-    EmptyStatement
+    ReturnStatement(Option<Box<Expression>>, Span),
+    EmptyStatement(Span)
 }
 
+#[allow(clippy::vec_box)]
 #[derive(PartialEq, Debug)]
 pub struct CompoundStatement {
     pub declarations: Vec<VarDeclaration>,
-    pub statements: Vec<Box<Statement>>
+    pub statements: Vec<Box<Statement>>,
+    pub span: Span,
 }
 
 #[derive(PartialEq, Debug)]
 pub enum VarDeclaration {
-    VarDeclaration(IdentifierType, String),
-    ArrDeclaration(IdentifierType, String, i32)
+    VarDeclaration(IdentifierType, String, Span),
+    ArrDeclaration(IdentifierType, String, i32, Span)
 }
 
 #[derive(PartialEq, Debug)]
 pub enum IfStatement {
-    IfStmt(Box<Expression>, Box<Statement>),
-    IfElseStmt(Box<Expression>, Box<Statement>, Box<Statement>)
+    IfStmt(Box<Expression>, Box<Statement>, Span),
+    IfElseStmt(Box<Expression>, Box<Statement>, Box<Statement>, Span)
 }
 
 #[derive(PartialEq, Debug)]
 pub struct WhileStatement {
     pub condition: Box<Expression>,
     pub statement: Box<Statement>,
+    pub span: Span,
 }
 
 #[derive(PartialEq, Debug)]
 pub enum Expression {
-    Assignment(Box<Var>, Box<Expression>),
-    Operation(Box<Expression>, Operator, Box<Expression>),
+    Assignment(Box<Var>, Box<Expression>, Span),
+    Operation(Box<Expression>, Operator, Box<Expression>, Span),
     Var(Box<Var>),
     Call(Box<FunctionCall>),
-    IntegerLiteral(i32)
+    IntegerLiteral(i32, Span)
 }
 
 #[derive(PartialEq, Debug)]
 pub enum Operator {
     Mul,
     Div,
+    Mod,
     Add,
     Sub,
     Gt,
@@ -86,21 +93,104 @@ pub enum Operator {
 
 #[derive(PartialEq, Debug)]
 pub enum Var {
-    Var(String),
-    ArrayAccess(String, Box<Expression>),
+    Var(String, Span),
+    ArrayAccess(String, Box<Expression>, Span),
 }
 
+#[allow(clippy::vec_box)]
 #[derive(PartialEq, Debug)]
 pub struct FunctionCall {
     pub name: String,
     pub args: Vec<Box<Expression>>,
+    pub span: Span,
 }
 
+#[allow(clippy::vec_box)]
 pub enum IdentifierFollow {
     FunctionCall(Vec<Box<Expression>>),
     ArrayAccess(Box<Expression>)
 }
 
+impl Param {
+    pub fn span(&self) -> Span {
+        match self {
+            Param::Var(_, _, span) => *span,
+            Param::ArrVar(_, _, span) => *span,
+        }
+    }
+}
+
+impl Statement {
+    pub fn span(&self) -> Span {
+        match self {
+            Statement::Expression(expression) => expression.span(),
+            Statement::CompoundStatement(compound_statement) => compound_statement.span,
+            Statement::IfStatement(if_statement) => if_statement.span(),
+            Statement::WhileStatement(while_statement) => while_statement.span,
+            Statement::ReturnStatement(_, span) => *span,
+            Statement::EmptyStatement(span) => *span,
+        }
+    }
+}
+
+impl VarDeclaration {
+    pub fn span(&self) -> Span {
+        match self {
+            VarDeclaration::VarDeclaration(_, _, span) => *span,
+            VarDeclaration::ArrDeclaration(_, _, _, span) => *span,
+        }
+    }
+}
+
+impl IfStatement {
+    pub fn span(&self) -> Span {
+        match self {
+            IfStatement::IfStmt(_, _, span) => *span,
+            IfStatement::IfElseStmt(_, _, _, span) => *span,
+        }
+    }
+}
+
+impl Expression {
+    pub fn span(&self) -> Span {
+        match self {
+            Expression::Assignment(_, _, span) => *span,
+            Expression::Operation(_, _, _, span) => *span,
+            Expression::Var(var) => var.span(),
+            Expression::Call(function_call) => function_call.span,
+            Expression::IntegerLiteral(_, span) => *span,
+        }
+    }
+}
+
+impl Operator {
+    pub fn symbol(&self) -> &'static str {
+        match self {
+            Operator::Mul => "*",
+            Operator::Div => "/",
+            Operator::Mod => "%",
+            Operator::Add => "+",
+            Operator::Sub => "-",
+            Operator::Gt => ">",
+            Operator::Ge => ">=",
+            Operator::Lt => "<",
+            Operator::Le => "<=",
+            Operator::Ne => "!=",
+            Operator::Eq => "==",
+            Operator::As => "=",
+        }
+    }
+}
+
+impl Var {
+    pub fn span(&self) -> Span {
+        match self {
+            Var::Var(_, span) => *span,
+            Var::ArrayAccess(_, _, span) => *span,
+        }
+    }
+}
+
 // impl Debug for FunctionCall {
 //     fn fmt(&self, fmt: &mut Formatter) -> Result<(), Error> {
 //         write!(fmt, "Function call")
@@ -0,0 +1,361 @@
+use crate::codegen::{codegen, CodeGen, CodegenOptions};
+use crate::emit::{link, OutputKind, TargetMachine, TempDir};
+use crate::interp::{interpret, Limits};
+use crate::jit;
+use crate::lexer::lex;
+use crate::optimize::{optimize, OptLevel};
+use crate::parser::{parse_program, parse_with_comments};
+use crate::printer::format_program;
+use crate::span::SourceFile;
+use crate::typecheck::{typecheck_program, TypecheckProgramResult};
+use std::fs;
+use std::io::{self, Read, Write};
+use std::path::Path;
+
+pub const USAGE: &str = "\
+usage: project [options] [<file>]
+       project run [options] <file>
+       project fmt [--check] [<file>]
+
+Compiles <file>, or standard input when it is missing or `-`. With `run`,
+compiles <file> in memory, runs its `main` and exits with the value it returns.
+With `fmt`, rewrites <file> in the canonical style, or prints standard input
+formatted.
+
+options:
+  -o <path>         write the output to <path> (`-` for standard output)
+  --emit=<kind>     one of tokens, ast, llvm-ir, bc, asm, obj, exe (default exe)
+  -S                same as --emit=asm
+  -O0, -O1, -O2, -O3, -Os
+                    optimization level (default -O0)
+  --bounds-check    stop the program when an array index is out of range
+  -g                include debug information for gdb and lldb
+  --interpret       with `run`, evaluate the program directly instead of
+                    compiling it
+  --check           with `fmt`, only report whether the source is formatted
+  -h, --help        print this message";
+
+pub const EXIT_SUCCESS: i32 = 0;
+pub const EXIT_COMPILE_ERROR: i32 = 1;
+pub const EXIT_USAGE_ERROR: i32 = 2;
+
+#[derive(PartialEq, Debug, Copy, Clone)]
+pub enum Emit {
+    Tokens,
+    Ast,
+    LlvmIr,
+    Bitcode,
+    Assembly,
+    Object,
+    Executable,
+}
+
+impl Emit {
+    fn from_name(name: &str) -> Option<Emit> {
+        match name {
+            "tokens" => Some(Emit::Tokens),
+            "ast" => Some(Emit::Ast),
+            "llvm-ir" => Some(Emit::LlvmIr),
+            "bc" => Some(Emit::Bitcode),
+            "asm" => Some(Emit::Assembly),
+            "obj" => Some(Emit::Object),
+            "exe" => Some(Emit::Executable),
+            _ => None,
+        }
+    }
+
+    /// Text outputs go to standard output unless `-o` says otherwise.
+    fn is_text(self) -> bool {
+        matches!(self, Emit::Tokens | Emit::Ast | Emit::LlvmIr)
+    }
+
+    fn extension(self) -> &'static str {
+        match self {
+            Emit::Bitcode => ".bc",
+            Emit::Assembly => ".s",
+            Emit::Object => ".o",
+            _ => "",
+        }
+    }
+}
+
+pub struct Options {
+    /// `None` reads the program from standard input.
+    pub input: Option<String>,
+    pub output: Option<String>,
+    pub emit: Emit,
+    pub codegen: CodegenOptions,
+    pub opt_level: OptLevel,
+    /// Run the program in the JIT instead of writing any output.
+    pub run: bool,
+    /// With `run`, use the tree-walking interpreter instead of the JIT.
+    pub interpret: bool,
+    /// Format the source instead of compiling it.
+    pub format: bool,
+    /// With `format`, fail if the source would change instead of writing it.
+    pub check: bool,
+    pub help: bool,
+}
+
+/// Parses the arguments after the program name.
+pub fn parse_args(args: &[String]) -> Result<Options, String> {
+    let mut options = Options {
+        input: None,
+        output: None,
+        emit: Emit::Executable,
+        codegen: CodegenOptions::default(),
+        opt_level: OptLevel::O0,
+        run: args.first().map(String::as_str) == Some("run"),
+        interpret: false,
+        format: args.first().map(String::as_str) == Some("fmt"),
+        check: false,
+        help: false,
+    };
+    let mut args = args.iter().skip((options.run || options.format) as usize);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-h" | "--help" => options.help = true,
+            "-o" => match args.next() {
+                Some(path) => options.output = Some(path.clone()),
+                None => return Err("`-o` needs a path".to_string()),
+            },
+            "-S" => options.emit = Emit::Assembly,
+            "--bounds-check" => options.codegen.bounds_checks = true,
+            "-g" => options.codegen.debug_info = true,
+            "--interpret" => options.interpret = true,
+            "--check" => options.check = true,
+            _ if arg.starts_with("-O") => {
+                options.opt_level = OptLevel::from_flag(arg)
+                    .ok_or_else(|| format!("unknown optimization level `{}`", arg))?;
+            }
+            "-" if options.input.is_none() => options.input = Some(arg.clone()),
+            _ if arg.starts_with("--emit=") => {
+                let name = &arg["--emit=".len()..];
+                options.emit = Emit::from_name(name)
+                    .ok_or_else(|| format!("unknown output kind `{}`", name))?;
+            }
+            _ if arg.starts_with('-') => return Err(format!("unknown option `{}`", arg)),
+            _ if options.input.is_none() => options.input = Some(arg.clone()),
+            _ => return Err(format!("unexpected argument `{}`", arg)),
+        }
+    }
+    if options.input.as_deref() == Some("-") {
+        options.input = None;
+    }
+    if options.run && (options.output.is_some() || options.emit != Emit::Executable) {
+        return Err(
+            "`run` does not write output, so it takes no `-o`, `-S` or `--emit`".to_string(),
+        );
+    }
+    if options.format
+        && (options.output.is_some()
+            || options.emit != Emit::Executable
+            || options.opt_level != OptLevel::O0
+            || options.codegen.bounds_checks
+            || options.codegen.debug_info)
+    {
+        return Err("`fmt` takes no options but `--check`".to_string());
+    }
+    if options.check && !options.format {
+        return Err("`--check` only applies to `fmt`".to_string());
+    }
+    if options.interpret && !options.run {
+        return Err("`--interpret` only applies to `run`".to_string());
+    }
+    if options.run && options.input.is_none() {
+        // Standard input is where the program's `input()` reads from.
+        return Err("`run` needs a source file".to_string());
+    }
+    if options.output.as_deref() == Some("-") && !options.emit.is_text() {
+        return Err("binary output cannot be written to standard output".to_string());
+    }
+    Ok(options)
+}
+
+/// Runs the compiler with the process arguments and returns its exit code.
+pub fn run(args: &[String]) -> i32 {
+    let options = match parse_args(args.get(1..).unwrap_or(&[])) {
+        Ok(options) => options,
+        Err(message) => {
+            eprintln!("error: {}\n\n{}", message, USAGE);
+            return EXIT_USAGE_ERROR;
+        }
+    };
+    if options.help {
+        println!("{}", USAGE);
+        return EXIT_SUCCESS;
+    }
+    match compile(&options) {
+        Ok(code) => code,
+        Err(()) => EXIT_COMPILE_ERROR,
+    }
+}
+
+/// Compiles according to `options`, printing every error to standard error.
+/// Returns the exit code: the program's own result with `run`.
+fn compile(options: &Options) -> Result<i32, ()> {
+    let (name, contents) = read_input(options.input.as_deref()).map_err(report)?;
+    let source = SourceFile::new(&name, &contents);
+    if options.emit == Emit::Tokens {
+        let lexed = lex(&contents).map_err(|diagnostics| {
+            for diagnostic in diagnostics {
+                eprintln!("{}", diagnostic.render(&source));
+            }
+        })?;
+        let mut text = String::new();
+        for (start, token, end) in lexed.tokens {
+            let (line, column) = source.line_col(start);
+            text.push_str(&format!(
+                "{}:{}\t{}\t{}\n",
+                line,
+                column,
+                token.kind(),
+                &contents[start..end]
+            ));
+        }
+        write_text(options.output.as_deref(), &text).map_err(report)?;
+        return Ok(EXIT_SUCCESS);
+    }
+    if options.format {
+        let (program, comments) = parse_with_comments(&contents).map_err(|diagnostics| {
+            for diagnostic in diagnostics {
+                eprintln!("{}", diagnostic.render(&source));
+            }
+        })?;
+        return format(
+            options,
+            &source,
+            &format_program(&program, &contents, &comments),
+        );
+    }
+    let program = parse_program(&contents).map_err(|diagnostics| {
+        for diagnostic in diagnostics {
+            eprintln!("{}", diagnostic.render(&source));
+        }
+    })?;
+    if options.emit == Emit::Ast {
+        write_text(options.output.as_deref(), &format!("{:#?}\n", program)).map_err(report)?;
+        return Ok(EXIT_SUCCESS);
+    }
+    if let TypecheckProgramResult::Failure(diagnostics) = typecheck_program(&program) {
+        for diagnostic in diagnostics {
+            eprintln!("{}", diagnostic.render(&source));
+        }
+        return Err(());
+    }
+    if options.interpret {
+        let mut input = io::BufReader::new(io::stdin());
+        return interpret(&program, Limits::default(), &mut input, &mut io::stdout()).map_err(
+            |error| {
+                eprintln!("{}", error.render(&source));
+            },
+        );
+    }
+    let machine = TargetMachine::host(options.opt_level).map_err(report)?;
+    let module = codegen(&program, &source, &options.codegen).map_err(|diagnostic| {
+        eprintln!("{}", diagnostic.render(&source));
+    })?;
+    optimize(&module, &machine);
+    if options.run {
+        return jit::run(&module).map_err(report);
+    }
+    let output = match &options.output {
+        Some(path) => path.clone(),
+        None if options.emit.is_text() => "-".to_string(),
+        None => format!(
+            "{}{}",
+            output_stem(options.input.as_deref()),
+            options.emit.extension()
+        ),
+    };
+    if options.input.as_deref() == Some(output.as_str()) {
+        report(format!(
+            "refusing to overwrite the input `{}`; pass `-o`",
+            output
+        ));
+        return Err(());
+    }
+    emit(&module, &machine, options.emit, &output).map_err(report)?;
+    Ok(EXIT_SUCCESS)
+}
+
+/// Writes the formatted source back to the input file, or to standard output
+/// when it came from standard input. With `--check` nothing is written and the
+/// exit code says whether the source was already formatted.
+fn format(options: &Options, source: &SourceFile, formatted: &str) -> Result<i32, ()> {
+    if options.check {
+        if formatted == source.contents {
+            return Ok(EXIT_SUCCESS);
+        }
+        report(format!("`{}` is not formatted", source.name));
+        return Ok(EXIT_COMPILE_ERROR);
+    }
+    match &options.input {
+        Some(_) if formatted == source.contents => {}
+        input => write_text(input.as_deref(), formatted).map_err(report)?,
+    }
+    Ok(EXIT_SUCCESS)
+}
+
+fn emit(module: &CodeGen, machine: &TargetMachine, kind: Emit, output: &str) -> Result<(), String> {
+    match kind {
+        Emit::LlvmIr => write_text(Some(output), &module.ir()),
+        Emit::Bitcode => {
+            if module.write_bitcode(output) {
+                Ok(())
+            } else {
+                Err(format!("could not write `{}`", output))
+            }
+        }
+        Emit::Assembly => machine.emit(module, output, OutputKind::Assembly),
+        Emit::Object => machine.emit(module, output, OutputKind::Object),
+        Emit::Executable => {
+            let directory = TempDir::new()?;
+            let object = directory.path().join("program.o");
+            let object = object.to_string_lossy();
+            machine.emit(module, &object, OutputKind::Object)?;
+            link(&object, output)
+        }
+        Emit::Tokens | Emit::Ast => unreachable!("handled before codegen"),
+    }
+}
+
+fn read_input(input: Option<&str>) -> Result<(String, String), String> {
+    match input {
+        Some(path) => fs::read_to_string(path)
+            .map(|contents| (path.to_string(), contents))
+            .map_err(|error| format!("could not read `{}`: {}", path, error)),
+        None => {
+            let mut contents = String::new();
+            io::stdin()
+                .read_to_string(&mut contents)
+                .map_err(|error| format!("could not read standard input: {}", error))?;
+            Ok(("<stdin>".to_string(), contents))
+        }
+    }
+}
+
+/// Writes to the file at `path`, or standard output for `None` and `-`.
+fn write_text(path: Option<&str>, text: &str) -> Result<(), String> {
+    match path {
+        None | Some("-") => io::stdout()
+            .write_all(text.as_bytes())
+            .map_err(|error| format!("could not write to standard output: {}", error)),
+        Some(path) => {
+            fs::write(path, text).map_err(|error| format!("could not write `{}`: {}", path, error))
+        }
+    }
+}
+
+/// The default output name: the input's file name without its extension, or
+/// `out` when reading standard input.
+fn output_stem(input: Option<&str>) -> String {
+    input
+        .and_then(|path| Path::new(path).file_stem())
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .unwrap_or_else(|| "out".to_string())
+}
+
+fn report(message: String) {
+    eprintln!("error: {}", message);
+}
@@ -1,513 +1,878 @@
 use crate::ast::*;
+use crate::debuginfo::DebugInfo;
+use crate::diagnostic::{Code, Diagnostic};
+use crate::runtime::BUILTINS;
+use crate::span::{SourceFile, Span};
+use crate::symbol_table;
 extern crate llvm_sys as llvm;
 
 use llvm::core::*;
+use llvm::prelude::{
+    LLVMBasicBlockRef, LLVMBuilderRef, LLVMContextRef, LLVMMetadataRef, LLVMModuleRef, LLVMTypeRef,
+    LLVMValueRef,
+};
 use llvm::*;
 use std::collections::HashMap;
-use std::ffi::CString;
-
-static mut RET_BLOCK: Option<prelude::LLVMBasicBlockRef> = Option::None;
-static mut RET_VAL: Option<prelude::LLVMValueRef> = Option::None;
-
-pub fn codegen(program: &Program, file_name: &str) {
-    unsafe {
-        let context = LLVMContextCreate();
-        let module = LLVMModuleCreateWithNameInContext(to_c_string(file_name), context);
-        let builder = LLVMCreateBuilderInContext(context);
-        let int_type = LLVMInt32Type();
-        let mut functions: HashMap<String, prelude::LLVMValueRef> = HashMap::new();
-        let mut named_values = Table {
-            scope: HashMap::new(),
-            parent: Option::None,
+use std::ffi::{CStr, CString};
+use std::ptr;
+
+#[derive(Default)]
+pub struct CodegenOptions {
+    /// Check every array index at run time, including through array
+    /// parameters, and stop the program with a message when it is out of
+    /// range.
+    pub bounds_checks: bool,
+    /// Attach DWARF debug information, so the program can be stepped
+    /// through in a debugger.
+    pub debug_info: bool,
+}
+
+/// Compiles a type-checked program into a fresh LLVM module.
+pub fn codegen<'a>(
+    program: &Program,
+    source: &'a SourceFile,
+    options: &CodegenOptions,
+) -> Result<CodeGen<'a>, Diagnostic> {
+    let mut codegen = CodeGen::new(source, options);
+    codegen.compile(program)?;
+    Ok(codegen)
+}
+
+/// Owns one LLVM context, module and builder, plus the state of the function
+/// being generated. Nothing is shared between instances, so separate
+/// programs can be compiled on separate threads.
+pub struct CodeGen<'a> {
+    context: LLVMContextRef,
+    module: LLVMModuleRef,
+    builder: LLVMBuilderRef,
+    // The `i32` every value in the language is, in `context`.
+    int_type: LLVMTypeRef,
+    source: &'a SourceFile,
+    bounds_trap: Option<LLVMValueRef>,
+    debug: Option<DebugInfo>,
+    functions: HashMap<String, LLVMValueRef>,
+    // Innermost scope last; globals live in the first one.
+    scopes: Vec<HashMap<String, LLVMValueRef>>,
+    // With bounds checks, the length passed after each array parameter of
+    // the function being generated.
+    param_lengths: HashMap<String, LLVMValueRef>,
+    function: LLVMValueRef,
+    ret_block: Option<LLVMBasicBlockRef>,
+    ret_val: Option<LLVMValueRef>,
+}
+
+impl<'a> CodeGen<'a> {
+    pub fn new(source: &'a SourceFile, options: &CodegenOptions) -> CodeGen<'a> {
+        let name = c_string(&source.name);
+        let mut codegen = unsafe {
+            let context = LLVMContextCreate();
+            let module = LLVMModuleCreateWithNameInContext(name.as_ptr(), context);
+            CodeGen {
+                context,
+                module,
+                builder: LLVMCreateBuilderInContext(context),
+                int_type: LLVMInt32TypeInContext(context),
+                source,
+                bounds_trap: None,
+                debug: options.debug_info.then(|| DebugInfo::new(context, module, source)),
+                functions: HashMap::new(),
+                scopes: vec![HashMap::new()],
+                param_lengths: HashMap::new(),
+                function: std::ptr::null_mut(),
+                ret_block: None,
+                ret_val: None,
+            }
         };
+        if options.bounds_checks {
+            codegen.bounds_trap = Some(codegen.codegen_bounds_trap());
+        }
+        codegen
+    }
+
+    pub fn module(&self) -> LLVMModuleRef {
+        self.module
+    }
+
+    /// The textual LLVM IR of the module.
+    pub fn ir(&self) -> String {
+        unsafe {
+            let ir = LLVMPrintModuleToString(self.module);
+            let text = CStr::from_ptr(ir).to_string_lossy().into_owned();
+            LLVMDisposeMessage(ir);
+            text
+        }
+    }
+
+    pub fn write_bitcode(&self, path: &str) -> bool {
+        let path = c_string(path);
+        unsafe { bit_writer::LLVMWriteBitcodeToFile(self.module, path.as_ptr()) == 0 }
+    }
+
+    pub fn compile(&mut self, program: &Program) -> Result<(), Diagnostic> {
         for var_declaration in &program.var_declarations {
-            match &var_declaration {
-                VarDeclaration::ArrDeclaration(_, name, size) => {
-                    LLVMAddGlobal(
-                        module,
-                        LLVMArrayType(int_type, *size as u32),
-                        to_c_string(name),
-                    );
-                    let g_var = LLVMGetNamedGlobal(module, to_c_string(&name));
-                    LLVMSetLinkage(g_var, LLVMLinkage::LLVMCommonLinkage);
-                    let zero = LLVMConstInt(LLVMInt32Type(), 0, 0);
-                    let mut array = Vec::new();
-                    let mut i = 0;
-                    while i < *size {
-                        array.push(zero);
-                        i = i + 1;
-                    }
-                    LLVMSetInitializer(g_var, LLVMConstArray(LLVMInt32Type(), array.as_mut_ptr(), *size as u32));
-                    named_values.insert(name.clone(), g_var);
+            self.codegen_global(var_declaration);
+        }
+        self.declare_builtins();
+        for function in &program.fun_declarations {
+            self.declare_function(function);
+        }
+        for function in &program.fun_declarations {
+            self.codegen_function(function);
+            self.verify_function(function)?;
+        }
+        if let Some(debug) = &mut self.debug {
+            debug.finalize();
+        }
+        self.verify()
+    }
+
+    /// Runs the LLVM verifier over the whole module.
+    pub fn verify(&self) -> Result<(), Diagnostic> {
+        match self.verifier_errors() {
+            None => Ok(()),
+            Some(errors) => Err(internal_error(
+                "the generated module failed LLVM verification",
+                Span::default(),
+                &errors,
+                &self.ir(),
+            )),
+        }
+    }
+
+    /// Checks a function as soon as it is generated, so malformed IR is
+    /// reported against the source function instead of crashing LLVM later.
+    /// The functions before it have passed and the ones after it have no
+    /// bodies yet, so the verifier's report is about this one.
+    pub fn verify_function(&self, function: &FunctionDeclaration) -> Result<(), Diagnostic> {
+        let value = self.functions[&function.function_name];
+        let broken = unsafe {
+            analysis::LLVMVerifyFunction(value, analysis::LLVMVerifierFailureAction::LLVMReturnStatusAction)
+                != 0
+        };
+        if !broken {
+            return Ok(());
+        }
+        let ir = unsafe {
+            let ir = LLVMPrintValueToString(value);
+            let text = CStr::from_ptr(ir).to_string_lossy().into_owned();
+            LLVMDisposeMessage(ir);
+            text
+        };
+        Err(internal_error(
+            &format!("LLVM rejected the code generated for function `{}`", function.function_name),
+            function.span,
+            &self.verifier_errors().unwrap_or_default(),
+            &ir,
+        ))
+    }
+
+    /// The verifier's report for the module, or `None` if it is well formed.
+    fn verifier_errors(&self) -> Option<String> {
+        unsafe {
+            let mut message = ptr::null_mut();
+            let broken = analysis::LLVMVerifyModule(
+                self.module,
+                analysis::LLVMVerifierFailureAction::LLVMReturnStatusAction,
+                &mut message,
+            ) != 0;
+            let text = CStr::from_ptr(message).to_string_lossy().trim_end().to_string();
+            LLVMDisposeMessage(message);
+            if broken {
+                Some(text)
+            } else {
+                None
+            }
+        }
+    }
+
+    fn const_int(&self, value: i32) -> LLVMValueRef {
+        unsafe { LLVMConstInt(self.int_type, value as u64, 0) }
+    }
+
+    fn lookup(&self, name: &str) -> LLVMValueRef {
+        self.scopes
+            .iter()
+            .rev()
+            .find_map(|scope| scope.get(name))
+            .copied()
+            .unwrap_or_else(|| panic!("`{}` was not declared; typecheck should have caught this", name))
+    }
+
+    fn insert(&mut self, name: &str, value: LLVMValueRef) {
+        self.scopes.last_mut().unwrap().insert(name.to_string(), value);
+    }
+
+    /// Points the debug location of the instructions built from here on at
+    /// `span`, when compiling with debug info.
+    fn set_location(&self, span: Span) {
+        if let Some(debug) = &self.debug {
+            let (line, column) = self.source.line_col(span.start);
+            unsafe {
+                LLVMSetCurrentDebugLocation2(self.builder, debug.location(line as u32, column as u32));
+            }
+        }
+    }
+
+    /// The debug info type matching an `i32`, `[N x i32]` or `i32*`.
+    fn debug_type(debug: &DebugInfo, value_type: LLVMTypeRef) -> LLVMMetadataRef {
+        unsafe {
+            match LLVMGetTypeKind(value_type) {
+                LLVMTypeKind::LLVMArrayTypeKind => debug.array_type(LLVMGetArrayLength(value_type)),
+                LLVMTypeKind::LLVMPointerTypeKind => debug.array_parameter_type(),
+                _ => debug.int_type(),
+            }
+        }
+    }
+
+    /// Describes the variable in the alloca `slot` to the debugger; `argument`
+    /// is the 1-based parameter number for parameters.
+    fn declare_variable(&self, slot: LLVMValueRef, name: &str, span: Span, argument: Option<u32>) {
+        if let Some(debug) = &self.debug {
+            let (line, column) = self.source.line_col(span.start);
+            unsafe {
+                debug.declare_variable(
+                    slot,
+                    name,
+                    Self::debug_type(debug, LLVMGetAllocatedType(slot)),
+                    argument,
+                    line as u32,
+                    column as u32,
+                    LLVMGetInsertBlock(self.builder),
+                );
+            }
+        }
+    }
+
+    fn current_block_terminated(&self) -> bool {
+        unsafe { !LLVMGetBasicBlockTerminator(LLVMGetInsertBlock(self.builder)).is_null() }
+    }
+
+    fn append_block(&self, name: &CStr) -> LLVMBasicBlockRef {
+        unsafe { LLVMAppendBasicBlockInContext(self.context, self.function, name.as_ptr()) }
+    }
+
+    /// Moves the builder to the end of a block created earlier, adding the
+    /// block to the current function first.
+    fn position_at(&self, block: LLVMBasicBlockRef) {
+        unsafe {
+            LLVMAppendExistingBasicBlock(self.function, block);
+            LLVMPositionBuilderAtEnd(self.builder, block);
+        }
+    }
+
+    fn branch_unless_terminated(&self, target: LLVMBasicBlockRef) {
+        if !self.current_block_terminated() {
+            unsafe {
+                LLVMBuildBr(self.builder, target);
+            }
+        }
+    }
+
+    /// Allocates stack space in the entry block, so a declaration inside a
+    /// loop does not grow the stack on every iteration.
+    fn build_entry_alloca(&self, value_type: LLVMTypeRef, name: &str) -> LLVMValueRef {
+        let name = c_string(name);
+        unsafe {
+            let builder = LLVMCreateBuilderInContext(self.context);
+            let entry = LLVMGetEntryBasicBlock(self.function);
+            let first = LLVMGetFirstInstruction(entry);
+            if first.is_null() {
+                LLVMPositionBuilderAtEnd(builder, entry);
+            } else {
+                LLVMPositionBuilderBefore(builder, first);
+            }
+            let alloca = LLVMBuildAlloca(builder, value_type, name.as_ptr());
+            LLVMSetInstDebugLocation(self.builder, alloca);
+            LLVMDisposeBuilder(builder);
+            alloca
+        }
+    }
+
+    fn codegen_global(&mut self, declaration: &VarDeclaration) {
+        let (name, global_type, initializer) = unsafe {
+            match declaration {
+                VarDeclaration::ArrDeclaration(_, name, size, _) => {
+                    let mut zeros = vec![self.const_int(0); *size as usize];
+                    (
+                        name,
+                        LLVMArrayType(self.int_type, *size as u32),
+                        LLVMConstArray(self.int_type, zeros.as_mut_ptr(), *size as u32),
+                    )
                 }
-                VarDeclaration::VarDeclaration(_, name) => {
-                    LLVMAddGlobal(module, int_type, to_c_string(&name));
-                    let g_var = LLVMGetNamedGlobal(module, to_c_string(&name));
-                    LLVMSetLinkage(g_var, LLVMLinkage::LLVMCommonLinkage);
-                    LLVMSetInitializer(g_var, LLVMConstInt(LLVMInt32Type(), 0, 0));
-                    named_values.insert(name.clone(), g_var);
+                VarDeclaration::VarDeclaration(_, name, _) => {
+                    (name, self.int_type, self.const_int(0))
                 }
             }
+        };
+        let c_name = c_string(name);
+        let global = unsafe {
+            let global = LLVMAddGlobal(self.module, global_type, c_name.as_ptr());
+            LLVMSetLinkage(global, LLVMLinkage::LLVMCommonLinkage);
+            LLVMSetInitializer(global, initializer);
+            global
+        };
+        self.insert(name, global);
+        if let Some(debug) = &self.debug {
+            let (line, _) = self.source.line_col(declaration.span().start);
+            debug.declare_global(global, name, Self::debug_type(debug, global_type), line as u32);
         }
+    }
 
-        for function in &program.fun_declarations {
-            let mut formal_vars: Vec<prelude::LLVMTypeRef> = Vec::new();
-            for arg in &function.params {
-                match &arg {
-                    Param::Var(_, _) => {
-                        formal_vars.push(LLVMInt32TypeInContext(context));
-                    }
-                    _ => {
-                        println!("Error, formal variable in not an int");
+    /// With bounds checks, an array parameter is passed as an `i32*` followed
+    /// by the array's length as an `i32`.
+    fn declare_function(&mut self, function: &FunctionDeclaration) {
+        let mut param_types = Vec::new();
+        for param in &function.params {
+            match param {
+                Param::Var(_, _, _) => param_types.push(self.int_type),
+                Param::ArrVar(_, _, _) => {
+                    param_types.push(unsafe { LLVMPointerType(self.int_type, 0) });
+                    if self.bounds_trap.is_some() {
+                        param_types.push(self.int_type);
                     }
                 }
             }
-            let function_type = match &function.return_type {
-                IdentifierType::Void => LLVMFunctionType(
-                    LLVMVoidTypeInContext(context),
-                    formal_vars.as_mut_ptr(),
-                    formal_vars.len().try_into().unwrap(),
-                    0,
-                ),
-                IdentifierType::Int => LLVMFunctionType(
-                    LLVMInt32TypeInContext(context),
-                    formal_vars.as_mut_ptr(),
-                    formal_vars.len().try_into().unwrap(),
-                    0,
-                ),
-            };
-            let llvm_function =
-                LLVMAddFunction(module, to_c_string(&function.function_name), function_type);
-            functions.insert(function.function_name.clone(), llvm_function);
         }
-        let mut i = 0;
-        let mut j = 0;
-        for function in &program.fun_declarations {
-            named_values = Table {
-                scope: HashMap::new(),
-                parent: Option::Some(Box::new(named_values)),
+        // A void `main` still gives the process an exit status, 0.
+        let return_type = match is_void_main(function) {
+            true => IdentifierType::Int,
+            false => function.return_type,
+        };
+        self.add_function(&function.function_name, return_type, param_types);
+    }
+
+    /// Declares the built-in functions, whose definitions come from the C
+    /// runtime at link time.
+    fn declare_builtins(&mut self) {
+        for builtin in BUILTINS {
+            let param_types = builtin
+                .params
+                .iter()
+                .map(|param| match param {
+                    symbol_table::Param::Var => self.int_type,
+                    symbol_table::Param::Arr => unsafe { LLVMPointerType(self.int_type, 0) },
+                })
+                .collect();
+            self.add_function(builtin.name, builtin.return_type, param_types);
+        }
+    }
+
+    fn add_function(&mut self, name: &str, return_type: IdentifierType, mut param_types: Vec<LLVMTypeRef>) {
+        let c_name = c_string(name);
+        unsafe {
+            let return_type = match return_type {
+                IdentifierType::Void => LLVMVoidTypeInContext(self.context),
+                IdentifierType::Int => self.int_type,
             };
-            let bb = LLVMAppendBasicBlockInContext(
-                context,
-                *functions.get_mut(&function.function_name).unwrap(),
-                b"entry\0".as_ptr() as *const _,
+            let function_type = LLVMFunctionType(
+                return_type,
+                param_types.as_mut_ptr(),
+                param_types.len() as u32,
+                0,
             );
-            LLVMPositionBuilderAtEnd(builder, bb);
-            for param in &program.fun_declarations[i].params {
-                match param {
-                    Param::Var(_, name) => {
-                        let inst = LLVMBuildAlloca(builder, int_type, to_c_string(&name));
-                        LLVMBuildStore(
-                            builder,
-                            LLVMGetParam(*functions.get_mut(&function.function_name).unwrap(), j),
-                            inst,
-                        );
-                        named_values.insert(name.clone(), inst);
-                    }
-                    _ => {
-                        println!("Invaldid param");
-                    }
-                }
-                j = j + 1;
-            }
-            RET_VAL = Option::Some(LLVMBuildAlloca(
-                builder,
-                LLVMInt32Type(),
-                to_c_string("ret_value"),
-            ));
-            named_values = codegen_compount_statement(
-                builder,
-                &function.body,
-                named_values,
-                &mut functions,
-                context,
+            let llvm_function = LLVMAddFunction(self.module, c_name.as_ptr(), function_type);
+            self.functions.insert(name.to_string(), llvm_function);
+        }
+    }
+
+    fn codegen_function(&mut self, function: &FunctionDeclaration) {
+        self.function = self.functions[&function.function_name];
+        self.ret_block = None;
+        if let Some(debug) = &mut self.debug {
+            let return_type = match function.return_type {
+                IdentifierType::Int => Some(debug.int_type()),
+                IdentifierType::Void => None,
+            };
+            let param_types: Vec<LLVMMetadataRef> = function
+                .params
+                .iter()
+                .map(|param| match param {
+                    Param::Var(_, _, _) => debug.int_type(),
+                    Param::ArrVar(_, _, _) => debug.array_parameter_type(),
+                })
+                .collect();
+            let (line, _) = self.source.line_col(function.span.start);
+            debug.begin_function(
+                self.function,
                 &function.function_name,
+                return_type,
+                &param_types,
+                line as u32,
             );
-            match RET_BLOCK {
-                Option::Some(block) => {
-                    if LLVMGetBasicBlockTerminator(LLVMGetLastBasicBlock(
-                        *functions.get_mut(&function.function_name).unwrap(),
-                    )).is_null()
-                    {
-                        LLVMBuildBr(builder, block);
-                    }
-                    LLVMAppendExistingBasicBlock(
-                        *functions.get_mut(&function.function_name).unwrap(),
-                        block,
-                    );
-                    LLVMPositionBuilderAtEnd(builder, block);
-                    match RET_VAL {
-                        Option::Some(val) => {
-                            let val_v = LLVMBuildLoad2(
-                                builder,
-                                LLVMInt32Type(),
-                                val,
-                                to_c_string("ret_val"),
-                            );
-                            LLVMBuildRet(builder, val_v);
-                        }
-                        Option::None => {
-                            LLVMBuildRetVoid(builder);
+        }
+        let entry = self.append_block(c"entry");
+        unsafe {
+            LLVMPositionBuilderAtEnd(self.builder, entry);
+        }
+        self.set_location(function.span);
+        self.scopes.push(HashMap::new());
+        self.param_lengths.clear();
+        let mut argument = 0;
+        for (j, param) in function.params.iter().enumerate() {
+            // Array parameters are spilled as an `i32*` slot, which is how
+            // `codegen_element_pointer` tells them apart from real arrays.
+            let (name, param_type) = match param {
+                Param::Var(_, name, _) => (name, self.int_type),
+                Param::ArrVar(_, name, _) => (name, unsafe { LLVMPointerType(self.int_type, 0) }),
+            };
+            let slot = self.build_entry_alloca(param_type, name);
+            unsafe {
+                LLVMBuildStore(self.builder, LLVMGetParam(self.function, argument), slot);
+            }
+            argument += 1;
+            if let (Param::ArrVar(_, _, _), Some(_)) = (param, self.bounds_trap) {
+                let length = unsafe { LLVMGetParam(self.function, argument) };
+                self.param_lengths.insert(name.clone(), length);
+                argument += 1;
+            }
+            self.declare_variable(slot, name, param.span(), Some(j as u32 + 1));
+            self.insert(name, slot);
+        }
+        self.ret_val = match function.return_type {
+            IdentifierType::Int => Some(self.build_entry_alloca(self.int_type, "ret_value")),
+            IdentifierType::Void => None,
+        };
+
+        self.codegen_compound_statement(&function.body);
+
+        // The epilogue belongs to the closing brace.
+        let end = function.body.span.end;
+        self.set_location(Span::new(end.saturating_sub(1), end));
+        unsafe {
+            match self.ret_block {
+                Some(block) => {
+                    self.branch_unless_terminated(block);
+                    self.position_at(block);
+                    match self.ret_val {
+                        Some(slot) => {
+                            let value =
+                                LLVMBuildLoad2(self.builder, self.int_type, slot, c"ret_val".as_ptr());
+                            LLVMBuildRet(self.builder, value);
                         }
+                        None => self.build_void_return(function),
                     }
                 }
-                Option::None => {
-                    LLVMBuildRetVoid(builder);
-                }
+                None if self.current_block_terminated() => {}
+                None => match function.return_type {
+                    IdentifierType::Void => self.build_void_return(function),
+                    // Typecheck rejects int functions that can fall off the
+                    // end, so this point is only reached after an endless loop.
+                    IdentifierType::Int => {
+                        LLVMBuildUnreachable(self.builder);
+                    }
+                },
             }
-            RET_VAL = Option::None;
-            RET_BLOCK = Option::None;
-            i = i + 1;
-            named_values = *named_values.parent.unwrap();
-        }
-        LLVMDumpModule(module);
-        // analysis::LLVMVerifyModule(
-        //     module,
-        //     analysis::LLVMVerifierFailureAction::LLVMPrintMessageAction,
-        //     Box::into_raw(Box::new("")) as *mut *mut i8,
-        // );
-        bit_writer::LLVMWriteBitcodeToFile(module, to_c_string("out.bc"));
+        }
+        self.scopes.pop();
+        if let Some(debug) = &mut self.debug {
+            debug.end_function();
+        }
     }
-}
 
-unsafe fn codegen_compount_statement(
-    builder: prelude::LLVMBuilderRef,
-    statement: &CompoundStatement,
-    named_values: Table,
-    functions: &mut HashMap<String, prelude::LLVMValueRef>,
-    context: prelude::LLVMContextRef,
-    function_name: &str,
-) -> Table {
-    let mut new_table = Table {
-        scope: HashMap::new(),
-        parent: Option::Some(Box::new(named_values)),
-    };
-    for declaration in &statement.declarations {
-        match declaration {
-            VarDeclaration::VarDeclaration(_, name) => {
-                let inst = LLVMBuildAlloca(builder, LLVMInt32Type(), to_c_string(&name));
-                new_table.insert(name.clone(), inst);
-            }
-            VarDeclaration::ArrDeclaration(_, name, size) => {
-                let arr_type = LLVMArrayType(LLVMInt32Type(), *size as u32);
-                let inst = LLVMBuildAlloca(builder, arr_type, to_c_string(&name));
-                new_table.insert(name.clone(), inst);
+    /// Returns from a void function, or with 0 from a void `main`.
+    fn build_void_return(&self, function: &FunctionDeclaration) {
+        unsafe {
+            if is_void_main(function) {
+                LLVMBuildRet(self.builder, self.const_int(0));
+            } else {
+                LLVMBuildRetVoid(self.builder);
             }
         }
     }
-    for statement in &statement.statements {
-        new_table = codegen_statement(
-            builder,
-            &statement,
-            new_table,
-            functions,
-            context,
-            function_name,
-        );
-    }
 
-    return *new_table.parent.unwrap();
-}
-
-unsafe fn codegen_statement(
-    builder: prelude::LLVMBuilderRef,
-    statement: &Statement,
-    mut named_values: Table,
-    functions: &mut HashMap<String, prelude::LLVMValueRef>,
-    context: prelude::LLVMContextRef,
-    function: &str,
-) -> Table {
-    match statement {
-        Statement::CompoundStatement(compound_statement) => {
-            return codegen_compount_statement(
-                builder,
-                compound_statement,
-                named_values,
-                functions,
-                context,
-                function,
-            )
-        }
-        Statement::EmptyStatement => {}
-        Statement::Expression(expression) => {
-            codegen_expression(builder, expression, &mut named_values, functions);
-        }
-        Statement::ReturnStatement(value) => match value {
-            Option::Some(expression) => {
-                let value_v = codegen_expression(builder, expression, &mut named_values, functions);
-                match RET_BLOCK {
-                    Option::Some(block) => {
-                        LLVMBuildStore(builder, value_v, RET_VAL.unwrap());
-                        LLVMBuildBr(builder, block);
+    fn codegen_compound_statement(&mut self, statement: &CompoundStatement) {
+        self.scopes.push(HashMap::new());
+        for declaration in &statement.declarations {
+            let (name, value_type) = match declaration {
+                VarDeclaration::VarDeclaration(_, name, _) => (name, self.int_type),
+                VarDeclaration::ArrDeclaration(_, name, size, _) => {
+                    (name, unsafe { LLVMArrayType(self.int_type, *size as u32) })
+                }
+            };
+            let slot = self.build_entry_alloca(value_type, name);
+            // Locals start out as 0 each time their block is entered, like
+            // globals and like in the interpreter. Arrays are cleared with a
+            // memset, since an aggregate store is expanded element by element.
+            unsafe {
+                match declaration {
+                    VarDeclaration::VarDeclaration(_, _, _) => {
+                        LLVMBuildStore(self.builder, self.const_int(0), slot);
                     }
-                    Option::None => {
-                        RET_BLOCK = Option::Some(LLVMCreateBasicBlockInContext(
-                            context,
-                            to_c_string("ret_block"),
-                        ));
-                        LLVMBuildStore(builder, value_v, RET_VAL.unwrap());
-                        LLVMBuildBr(builder, RET_BLOCK.unwrap());
+                    VarDeclaration::ArrDeclaration(_, _, _, _) => {
+                        let zero = LLVMConstInt(LLVMInt8TypeInContext(self.context), 0, 0);
+                        LLVMBuildMemSet(self.builder, slot, zero, LLVMSizeOf(value_type), 4);
                     }
                 }
             }
-            Option::None => match RET_BLOCK {
-                Option::Some(block) => {
-                    LLVMBuildBr(builder, block);
+            self.declare_variable(slot, name, declaration.span(), None);
+            self.insert(name, slot);
+        }
+        for statement in &statement.statements {
+            self.codegen_statement(statement);
+        }
+        self.scopes.pop();
+    }
+
+    fn codegen_statement(&mut self, statement: &Statement) {
+        // Code after a `return` still has to go somewhere; give it a block
+        // nothing branches to.
+        if self.current_block_terminated() {
+            let dead_block = self.append_block(c"after_return");
+            unsafe {
+                LLVMPositionBuilderAtEnd(self.builder, dead_block);
+            }
+        }
+        self.set_location(statement.span());
+        match statement {
+            Statement::CompoundStatement(compound_statement) => {
+                if let Some(debug) = &mut self.debug {
+                    let (line, column) = self.source.line_col(compound_statement.span.start);
+                    debug.begin_block(line as u32, column as u32);
                 }
-                Option::None => {
-                    RET_BLOCK = Option::Some(LLVMCreateBasicBlockInContext(
-                        context,
-                        to_c_string("ret_block"),
-                    ));
-                    LLVMBuildBr(builder, RET_BLOCK.unwrap());
+                self.codegen_compound_statement(compound_statement);
+                if let Some(debug) = &mut self.debug {
+                    debug.end_block();
                 }
-            },
-        },
-        Statement::IfStatement(if_statement) => match &**if_statement {
-            IfStatement::IfStmt(cond, stmt) => {
-                let cond_v = codegen_expression(builder, cond, &mut named_values, functions);
-                let then_block = LLVMAppendBasicBlock(
-                    *functions.get_mut(function).unwrap(),
-                    to_c_string("then_block"),
-                );
-                let merge_block =
-                    LLVMCreateBasicBlockInContext(context, to_c_string("merge_block"));
-                LLVMBuildCondBr(builder, cond_v, then_block, merge_block);
-                LLVMPositionBuilderAtEnd(builder, then_block);
-                named_values =
-                    codegen_statement(builder, stmt, named_values, functions, context, function);
-                if LLVMGetBasicBlockTerminator(LLVMGetLastBasicBlock(
-                    *functions.get_mut(function).unwrap(),
-                )).is_null()
-                {
-                    LLVMBuildBr(builder, merge_block);
-                }
-                LLVMAppendExistingBasicBlock(*functions.get_mut(function).unwrap(), merge_block);
-                LLVMPositionBuilderAtEnd(builder, merge_block);
             }
-            IfStatement::IfElseStmt(cond, stmt1, stmt2) => {
-                let cond_v = codegen_expression(builder, cond, &mut named_values, functions);
-                let then_block = LLVMAppendBasicBlock(
-                    *functions.get_mut(function).unwrap(),
-                    to_c_string("then_block"),
-                );
-                let else_block = LLVMCreateBasicBlockInContext(context, to_c_string("else_block"));
-                let merge_block =
-                    LLVMCreateBasicBlockInContext(context, to_c_string("merge_block"));
-                LLVMBuildCondBr(builder, cond_v, then_block, else_block);
-                LLVMPositionBuilderAtEnd(builder, then_block);
-                named_values =
-                    codegen_statement(builder, stmt1, named_values, functions, context, function);
-                if LLVMGetBasicBlockTerminator(LLVMGetLastBasicBlock(
-                    *functions.get_mut(function).unwrap(),
-                )).is_null()
-                {
-                    LLVMBuildBr(builder, merge_block);
+            Statement::EmptyStatement(_) => {}
+            Statement::Expression(expression) => {
+                self.codegen_expression(expression);
+            }
+            Statement::ReturnStatement(value, _) => {
+                if let Some(expression) = value {
+                    let value_v = self.codegen_expression(expression);
+                    unsafe {
+                        LLVMBuildStore(self.builder, value_v, self.ret_val.unwrap());
+                    }
                 }
-                LLVMAppendExistingBasicBlock(*functions.get_mut(function).unwrap(), else_block);
-                LLVMPositionBuilderAtEnd(builder, else_block);
-                named_values =
-                    codegen_statement(builder, stmt2, named_values, functions, context, function);
-                if LLVMGetBasicBlockTerminator(LLVMGetLastBasicBlock(
-                    *functions.get_mut(function).unwrap(),
-                )).is_null()
-                {
-                    LLVMBuildBr(builder, merge_block);
+                let ret_block = match self.ret_block {
+                    Some(block) => block,
+                    None => unsafe {
+                        let block = LLVMCreateBasicBlockInContext(self.context, c"ret_block".as_ptr());
+                        self.ret_block = Some(block);
+                        block
+                    },
+                };
+                unsafe {
+                    LLVMBuildBr(self.builder, ret_block);
                 }
-                LLVMAppendExistingBasicBlock(*functions.get_mut(function).unwrap(), merge_block);
-                LLVMPositionBuilderAtEnd(builder, merge_block);
             }
-        },
-        Statement::WhileStatement(stmt) => {
-            let cond_block = LLVMAppendBasicBlock(
-                *functions.get_mut(function).unwrap(),
-                to_c_string("cond_block"),
-            );
-            let loop_block = LLVMAppendBasicBlock(
-                *functions.get_mut(function).unwrap(),
-                to_c_string("loop_block"),
-            );
-            let merge_block = LLVMCreateBasicBlockInContext(context, to_c_string("merge_block"));
-            LLVMPositionBuilderAtEnd(builder, cond_block);
-            let cond_v = codegen_expression(builder, &stmt.condition, &mut named_values, functions);
-            LLVMBuildCondBr(builder, cond_v, loop_block, merge_block);
-            LLVMAppendExistingBasicBlock(*functions.get_mut(function).unwrap(), loop_block);
-            LLVMPositionBuilderAtEnd(builder, loop_block);
-            named_values = codegen_statement(
-                builder,
-                &stmt.statement,
-                named_values,
-                functions,
-                context,
-                function,
-            );
-            if LLVMGetBasicBlockTerminator(LLVMGetLastBasicBlock(
-                *functions.get_mut(function).unwrap(),
-            )).is_null()
-            {
-                LLVMBuildBr(builder, cond_block);
+            Statement::IfStatement(if_statement) => match &**if_statement {
+                IfStatement::IfStmt(cond, stmt, _) => {
+                    let cond_v = self.codegen_condition(cond);
+                    let then_block = self.append_block(c"then_block");
+                    unsafe {
+                        let merge_block =
+                            LLVMCreateBasicBlockInContext(self.context, c"merge_block".as_ptr());
+                        LLVMBuildCondBr(self.builder, cond_v, then_block, merge_block);
+                        LLVMPositionBuilderAtEnd(self.builder, then_block);
+                        self.codegen_statement(stmt);
+                        self.branch_unless_terminated(merge_block);
+                        self.position_at(merge_block);
+                    }
+                }
+                IfStatement::IfElseStmt(cond, stmt1, stmt2, _) => {
+                    let cond_v = self.codegen_condition(cond);
+                    let then_block = self.append_block(c"then_block");
+                    unsafe {
+                        let else_block =
+                            LLVMCreateBasicBlockInContext(self.context, c"else_block".as_ptr());
+                        let merge_block =
+                            LLVMCreateBasicBlockInContext(self.context, c"merge_block".as_ptr());
+                        LLVMBuildCondBr(self.builder, cond_v, then_block, else_block);
+                        LLVMPositionBuilderAtEnd(self.builder, then_block);
+                        self.codegen_statement(stmt1);
+                        self.branch_unless_terminated(merge_block);
+                        self.position_at(else_block);
+                        self.codegen_statement(stmt2);
+                        self.branch_unless_terminated(merge_block);
+                        self.position_at(merge_block);
+                    }
+                }
+            },
+            Statement::WhileStatement(stmt) => {
+                let cond_block = self.append_block(c"cond_block");
+                unsafe {
+                    let loop_block = LLVMCreateBasicBlockInContext(self.context, c"loop_block".as_ptr());
+                    let merge_block =
+                        LLVMCreateBasicBlockInContext(self.context, c"merge_block".as_ptr());
+                    LLVMBuildBr(self.builder, cond_block);
+                    LLVMPositionBuilderAtEnd(self.builder, cond_block);
+                    let cond_v = self.codegen_condition(&stmt.condition);
+                    LLVMBuildCondBr(self.builder, cond_v, loop_block, merge_block);
+                    self.position_at(loop_block);
+                    self.codegen_statement(&stmt.statement);
+                    self.set_location(stmt.span);
+                    self.branch_unless_terminated(cond_block);
+                    self.position_at(merge_block);
+                }
             }
-            LLVMAppendExistingBasicBlock(*functions.get_mut(function).unwrap(), merge_block);
-            LLVMPositionBuilderAtEnd(builder, merge_block);
         }
     }
-    return named_values;
-}
 
-unsafe fn codegen_expression(
-    builder: prelude::LLVMBuilderRef,
-    expression: &Expression,
-    named_values: &mut Table,
-    functions: &mut HashMap<String, prelude::LLVMValueRef>,
-) -> prelude::LLVMValueRef {
-    match expression {
-        Expression::IntegerLiteral(value) => LLVMConstInt(LLVMInt32Type(), *value as u64, 0),
-        Expression::Operation(lhs, op, rhs) => {
-            let lhs_v = codegen_expression(builder, lhs, named_values, functions);
-            let rhs_v = codegen_expression(builder, rhs, named_values, functions);
-            match op {
-                Operator::Add => LLVMBuildAdd(builder, lhs_v, rhs_v, to_c_string("temp_add")),
-                Operator::Sub => LLVMBuildSub(builder, lhs_v, rhs_v, to_c_string("temp_sum")),
-                Operator::Div => LLVMBuildUDiv(builder, lhs_v, rhs_v, to_c_string("temp_div")),
-                Operator::Mul => LLVMBuildMul(builder, lhs_v, rhs_v, to_c_string("temp_mul")),
-                Operator::Eq => LLVMBuildICmp(
-                    builder,
-                    LLVMIntPredicate::LLVMIntEQ,
-                    lhs_v,
-                    rhs_v,
-                    to_c_string("temp_EQ"),
-                ),
-                Operator::Ne => LLVMBuildICmp(
-                    builder,
-                    LLVMIntPredicate::LLVMIntNE,
-                    lhs_v,
-                    rhs_v,
-                    to_c_string("temp_NE"),
-                ),
-                Operator::Ge => LLVMBuildICmp(
-                    builder,
-                    LLVMIntPredicate::LLVMIntSGE,
-                    lhs_v,
-                    rhs_v,
-                    to_c_string("temp_GE"),
-                ),
-                Operator::Gt => LLVMBuildICmp(
-                    builder,
-                    LLVMIntPredicate::LLVMIntSGT,
-                    lhs_v,
-                    rhs_v,
-                    to_c_string("temp_GT"),
-                ),
-                Operator::Le => LLVMBuildICmp(
-                    builder,
-                    LLVMIntPredicate::LLVMIntSLE,
-                    lhs_v,
-                    rhs_v,
-                    to_c_string("temp_LE"),
-                ),
-                Operator::Lt => LLVMBuildICmp(
-                    builder,
-                    LLVMIntPredicate::LLVMIntSLT,
-                    lhs_v,
-                    rhs_v,
-                    to_c_string("temp_LT"),
-                ),
-                Operator::As => {
-                    println!("Something went wrong");
-                    LLVMConstInt(LLVMInt32Type(), 0, 0)
+    /// Lowers an `if` or `while` condition to the `i1` a branch needs: any
+    /// non-zero int is true.
+    fn codegen_condition(&mut self, expression: &Expression) -> LLVMValueRef {
+        let value = self.codegen_expression(expression);
+        unsafe {
+            LLVMBuildICmp(
+                self.builder,
+                LLVMIntPredicate::LLVMIntNE,
+                value,
+                self.const_int(0),
+                c"condition".as_ptr(),
+            )
+        }
+    }
+
+    fn codegen_expression(&mut self, expression: &Expression) -> LLVMValueRef {
+        match expression {
+            Expression::IntegerLiteral(value, _) => self.const_int(*value),
+            Expression::Operation(lhs, op, rhs, _) => {
+                let lhs_v = self.codegen_expression(lhs);
+                let rhs_v = self.codegen_expression(rhs);
+                let builder = self.builder;
+                unsafe {
+                    let predicate = match op {
+                        Operator::Add => return LLVMBuildAdd(builder, lhs_v, rhs_v, c"temp_add".as_ptr()),
+                        Operator::Sub => return LLVMBuildSub(builder, lhs_v, rhs_v, c"temp_sum".as_ptr()),
+                        Operator::Div => return LLVMBuildSDiv(builder, lhs_v, rhs_v, c"temp_div".as_ptr()),
+                        Operator::Mod => return LLVMBuildSRem(builder, lhs_v, rhs_v, c"temp_mod".as_ptr()),
+                        Operator::Mul => return LLVMBuildMul(builder, lhs_v, rhs_v, c"temp_mul".as_ptr()),
+                        Operator::Eq => LLVMIntPredicate::LLVMIntEQ,
+                        Operator::Ne => LLVMIntPredicate::LLVMIntNE,
+                        Operator::Ge => LLVMIntPredicate::LLVMIntSGE,
+                        Operator::Gt => LLVMIntPredicate::LLVMIntSGT,
+                        Operator::Le => LLVMIntPredicate::LLVMIntSLE,
+                        Operator::Lt => LLVMIntPredicate::LLVMIntSLT,
+                        Operator::As => unreachable!("the parser never produces Operator::As"),
+                    };
+                    // Comparisons are ints like everything else, so widen the i1.
+                    let cmp_v = LLVMBuildICmp(builder, predicate, lhs_v, rhs_v, c"temp_cmp".as_ptr());
+                    LLVMBuildZExt(builder, cmp_v, self.int_type, c"temp_bool".as_ptr())
                 }
             }
-        }
-        Expression::Assignment(lhs, rhs) => {
-            let rhs_v = codegen_expression(builder, rhs, named_values, functions);
-            match &**lhs {
-                Var::Var(name) => {
-                    let target = named_values.get(&name);
-                    LLVMBuildStore(builder, rhs_v, target)
+            Expression::Assignment(lhs, rhs, _) => {
+                let rhs_v = self.codegen_expression(rhs);
+                let target = match &**lhs {
+                    Var::Var(name, _) => self.lookup(name),
+                    Var::ArrayAccess(name, index, span) => {
+                        let index_v = self.codegen_expression(index);
+                        self.codegen_element_pointer(name, index_v, *span)
+                    }
+                };
+                // Like in C, the assignment is an expression with the stored value.
+                unsafe {
+                    LLVMBuildStore(self.builder, rhs_v, target);
                 }
-                Var::ArrayAccess(name, index) => {
-                    let index_v = codegen_expression(builder, index, named_values, functions);
-                    let zero = LLVMConstInt(LLVMInt32Type(), 0, 0);
-                    let mut indicies: Vec<prelude::LLVMValueRef> = Vec::new();
-                    indicies.push(zero);
-                    indicies.push(index_v);
-                    let lhs_v = LLVMBuildInBoundsGEP2(
-                        builder,
-                        LLVMInt32Type(),
-                        named_values.get(&name),
-                        indicies.as_mut_ptr(),
-                        1,
-                        to_c_string("array_access"),
-                    );
-                    LLVMBuildStore(builder, rhs_v, lhs_v)
+                rhs_v
+            }
+            Expression::Var(var) => {
+                let pointer = match &**var {
+                    Var::Var(name, _) => self.lookup(name),
+                    Var::ArrayAccess(name, index, span) => {
+                        let index_v = self.codegen_expression(index);
+                        self.codegen_element_pointer(name, index_v, *span)
+                    }
+                };
+                unsafe {
+                    LLVMBuildLoad2(self.builder, self.int_type, pointer, c"temp_var_load".as_ptr())
+                }
+            }
+            Expression::Call(function_call) => {
+                let function = self.functions[&function_call.name];
+                let mut args: Vec<LLVMValueRef> = Vec::new();
+                for arg in &function_call.args {
+                    let param_type = unsafe { LLVMTypeOf(LLVMGetParam(function, args.len() as u32)) };
+                    let is_array_param =
+                        unsafe { LLVMGetTypeKind(param_type) == LLVMTypeKind::LLVMPointerTypeKind };
+                    match &**arg {
+                        Expression::Var(var) if is_array_param => match &**var {
+                            Var::Var(name, _) => {
+                                args.push(self.codegen_array_decay(name));
+                                if self.bounds_trap.is_some() {
+                                    args.push(self.codegen_array_length(name));
+                                }
+                            }
+                            Var::ArrayAccess(_, _, _) => {
+                                unreachable!("typecheck only passes whole arrays to array parameters")
+                            }
+                        },
+                        _ => args.push(self.codegen_expression(arg)),
+                    }
+                }
+                unsafe {
+                    let function_type = LLVMGetElementType(LLVMTypeOf(function));
+                    // A call that produces no value must not be named.
+                    let name = match LLVMGetTypeKind(LLVMGetReturnType(function_type)) {
+                        LLVMTypeKind::LLVMVoidTypeKind => c"",
+                        _ => c"temp_call",
+                    };
+                    LLVMBuildCall2(
+                        self.builder,
+                        function_type,
+                        function,
+                        args.as_mut_ptr(),
+                        args.len() as u32,
+                        name.as_ptr(),
+                    )
                 }
             }
         }
-        Expression::Var(var) => match &**var {
-            Var::ArrayAccess(name, index) => {
-                let index_v = codegen_expression(builder, index, named_values, functions);
-                let zero = LLVMConstInt(LLVMInt32Type(), 0, 0);
-                let mut indicies: Vec<prelude::LLVMValueRef> = Vec::new();
-                indicies.push(zero);
-                indicies.push(zero);
-                let ptr = LLVMBuildInBoundsGEP2(
-                    builder,
-                    LLVMInt32Type(),
-                    named_values.get(&name),
-                    indicies.as_mut_ptr(),
+    }
+
+    /// Returns a pointer to element `index` of the array `name`, which is
+    /// either an `[N x i32]` alloca or global, or the `i32*` slot of an array
+    /// parameter.
+    fn codegen_element_pointer(&mut self, name: &str, index: LLVMValueRef, span: Span) -> LLVMValueRef {
+        if let Some(trap) = self.bounds_trap {
+            let length = self.codegen_array_length(name);
+            self.codegen_bounds_check(trap, name, index, length, span);
+        }
+        let array = self.lookup(name);
+        unsafe {
+            let pointee = LLVMGetElementType(LLVMTypeOf(array));
+            if LLVMGetTypeKind(pointee) == LLVMTypeKind::LLVMPointerTypeKind {
+                let base = LLVMBuildLoad2(self.builder, pointee, array, c"array_param".as_ptr());
+                let mut indices = [index];
+                return LLVMBuildInBoundsGEP2(
+                    self.builder,
+                    self.int_type,
+                    base,
+                    indices.as_mut_ptr(),
                     1,
-                    to_c_string("array_access"),
+                    c"array_access".as_ptr(),
                 );
-                return LLVMBuildLoad2(builder, LLVMInt32Type(), ptr, to_c_string("temp_var_load"));
             }
-            Var::Var(name) => {
-                return LLVMBuildLoad2(
-                    builder,
-                    LLVMInt32Type(),
-                    named_values.get(&name),
-                    to_c_string("temp_var_load"),
-                );
+            let mut indices = [self.const_int(0), index];
+            LLVMBuildInBoundsGEP2(
+                self.builder,
+                pointee,
+                array,
+                indices.as_mut_ptr(),
+                2,
+                c"array_access".as_ptr(),
+            )
+        }
+    }
+
+    /// Lowers a whole array passed as an argument to an `i32*` to its first
+    /// element.
+    fn codegen_array_decay(&mut self, name: &str) -> LLVMValueRef {
+        let array = self.lookup(name);
+        unsafe {
+            let pointee = LLVMGetElementType(LLVMTypeOf(array));
+            if LLVMGetTypeKind(pointee) == LLVMTypeKind::LLVMPointerTypeKind {
+                return LLVMBuildLoad2(self.builder, pointee, array, c"array_param".as_ptr());
             }
-        },
-        Expression::Call(function_call) => {
-            let function = *functions.get_mut(&function_call.name).unwrap();
-            let mut args: Vec<prelude::LLVMValueRef> = Vec::new();
-            for arg in &function_call.args {
-                args.push(codegen_expression(builder, arg, named_values, functions));
+            let mut indices = [self.const_int(0), self.const_int(0)];
+            LLVMBuildInBoundsGEP2(
+                self.builder,
+                pointee,
+                array,
+                indices.as_mut_ptr(),
+                2,
+                c"array_decay".as_ptr(),
+            )
+        }
+    }
+
+    /// The length of the array `name`: its declared size, or for an array
+    /// parameter the length passed with it.
+    fn codegen_array_length(&self, name: &str) -> LLVMValueRef {
+        let array = self.lookup(name);
+        unsafe {
+            let pointee = LLVMGetElementType(LLVMTypeOf(array));
+            if LLVMGetTypeKind(pointee) == LLVMTypeKind::LLVMPointerTypeKind {
+                return self.param_lengths[name];
             }
+            self.const_int(LLVMGetArrayLength(pointee) as i32)
+        }
+    }
+
+    /// Branches to the bounds trap unless `0 <= index < length`. A single
+    /// unsigned comparison covers both ends, since negative indices wrap to
+    /// values above any array length.
+    fn codegen_bounds_check(
+        &mut self,
+        trap: LLVMValueRef,
+        name: &str,
+        index: LLVMValueRef,
+        length: LLVMValueRef,
+        span: Span,
+    ) {
+        let location = c_string(&self.source.location(span));
+        let array_name = c_string(name);
+        unsafe {
+            let in_bounds = LLVMBuildICmp(
+                self.builder,
+                LLVMIntPredicate::LLVMIntULT,
+                index,
+                length,
+                c"in_bounds".as_ptr(),
+            );
+            let fail_block = self.append_block(c"bounds_fail");
+            let ok_block = self.append_block(c"bounds_ok");
+            LLVMBuildCondBr(self.builder, in_bounds, ok_block, fail_block);
+
+            LLVMPositionBuilderAtEnd(self.builder, fail_block);
+            let location_v =
+                LLVMBuildGlobalStringPtr(self.builder, location.as_ptr(), c"location".as_ptr());
+            let array_name_v =
+                LLVMBuildGlobalStringPtr(self.builder, array_name.as_ptr(), c"array_name".as_ptr());
+            let mut args = [location_v, array_name_v, index, length];
             LLVMBuildCall2(
-                builder,
-                LLVMInt32Type(),
-                function,
+                self.builder,
+                LLVMGetElementType(LLVMTypeOf(trap)),
+                trap,
                 args.as_mut_ptr(),
-                args.len().try_into().unwrap(),
-                to_c_string("temp_call"),
-            )
+                args.len() as u32,
+                c"".as_ptr(),
+            );
+            LLVMBuildUnreachable(self.builder);
+
+            LLVMPositionBuilderAtEnd(self.builder, ok_block);
+        }
+    }
+
+    /// Declares the runtime's `__bounds_check_failed`, which failed bounds
+    /// checks call with the location, the array's name, the index and the
+    /// length. It reports the error and does not return.
+    fn codegen_bounds_trap(&mut self) -> LLVMValueRef {
+        let int_type = self.int_type;
+        unsafe {
+            let string_type = LLVMPointerType(LLVMInt8TypeInContext(self.context), 0);
+            let mut trap_params = [string_type, string_type, int_type, int_type];
+            let trap_type =
+                LLVMFunctionType(LLVMVoidTypeInContext(self.context), trap_params.as_mut_ptr(), 4, 0);
+            LLVMAddFunction(self.module, c"__bounds_check_failed".as_ptr(), trap_type)
         }
     }
 }
 
-fn to_c_string(string: &str) -> *mut i8 {
-    return CString::new(string.as_bytes()).unwrap().into_raw() as *mut i8;
+impl Drop for CodeGen<'_> {
+    fn drop(&mut self) {
+        // The debug info builder refers into the module, so it goes first.
+        self.debug = None;
+        unsafe {
+            LLVMDisposeBuilder(self.builder);
+            LLVMDisposeModule(self.module);
+            LLVMContextDispose(self.context);
+        }
+    }
 }
 
-struct Table {
-    scope: HashMap<String, prelude::LLVMValueRef>,
-    parent: Option<Box<Table>>,
+fn is_void_main(function: &FunctionDeclaration) -> bool {
+    function.function_name == "main" && function.return_type == IdentifierType::Void
 }
 
-impl Table {
-    fn get(&mut self, key: &str) -> prelude::LLVMValueRef {
-        match self.scope.get_mut(key) {
-            Option::Some(value) => *value,
-            None => match &mut self.parent {
-                Some(parent) => parent.get(key),
-                None => panic!(),
-            },
-        }
-    }
+fn internal_error(message: &str, span: Span, errors: &str, ir: &str) -> Diagnostic {
+    Diagnostic::error(Code::InternalError, &format!("internal compiler error: {}", message), span)
+        .with_note("this is a bug in the compiler, not in the program")
+        .with_note(&format!("the LLVM verifier reported:\n{}", errors))
+        .with_note(&format!("the offending IR:\n{}", ir.trim_end()))
+}
 
-    fn insert(&mut self, key: String, value: prelude::LLVMValueRef) {
-        self.scope.insert(key, value);
-    }
+fn c_string(string: &str) -> CString {
+    CString::new(string.as_bytes()).unwrap()
 }
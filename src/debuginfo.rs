@@ -0,0 +1,321 @@
+extern crate llvm_sys as llvm;
+
+use crate::span::SourceFile;
+use llvm::core::*;
+use llvm::debuginfo::*;
+use llvm::prelude::{
+    LLVMBasicBlockRef, LLVMContextRef, LLVMDIBuilderRef, LLVMMetadataRef, LLVMModuleRef,
+    LLVMValueRef,
+};
+use llvm::LLVMModuleFlagBehavior;
+use std::env;
+use std::mem;
+use std::ptr;
+
+// In LLVM's C API, but not bound by llvm-sys 130.
+extern "C" {
+    fn LLVMDIBuilderFinalizeSubprogram(builder: LLVMDIBuilderRef, subprogram: LLVMMetadataRef);
+}
+
+const DW_ATE_SIGNED: LLVMDWARFTypeEncoding = 0x05;
+
+/// The DWARF descriptors `-g` attaches to a module: one compile unit for the
+/// source file, a subprogram per function and a variable per alloca.
+/// `CodeGen` drives it while it builds the IR, and reports positions as
+/// 1-based lines and columns.
+pub struct DebugInfo {
+    builder: LLVMDIBuilderRef,
+    context: LLVMContextRef,
+    file: LLVMMetadataRef,
+    compile_unit: LLVMMetadataRef,
+    int_type: LLVMMetadataRef,
+    // The current function's subprogram first, innermost lexical block last.
+    scopes: Vec<LLVMMetadataRef>,
+    finalized: bool,
+}
+
+impl DebugInfo {
+    pub fn new(context: LLVMContextRef, module: LLVMModuleRef, source: &SourceFile) -> DebugInfo {
+        let directory = env::current_dir()
+            .map(|directory| directory.to_string_lossy().into_owned())
+            .unwrap_or_else(|_| ".".to_string());
+        let producer = concat!("project ", env!("CARGO_PKG_VERSION"));
+        unsafe {
+            let version = LLVMValueAsMetadata(LLVMConstInt(
+                LLVMInt32TypeInContext(context),
+                LLVMDebugMetadataVersion() as u64,
+                0,
+            ));
+            let key = "Debug Info Version";
+            LLVMAddModuleFlag(
+                module,
+                LLVMModuleFlagBehavior::LLVMModuleFlagBehaviorWarning,
+                key.as_ptr() as *const _,
+                key.len(),
+                version,
+            );
+
+            let builder = LLVMCreateDIBuilder(module);
+            let file = LLVMDIBuilderCreateFile(
+                builder,
+                source.name.as_ptr() as *const _,
+                source.name.len(),
+                directory.as_ptr() as *const _,
+                directory.len(),
+            );
+            let compile_unit = LLVMDIBuilderCreateCompileUnit(
+                builder,
+                LLVMDWARFSourceLanguage::LLVMDWARFSourceLanguageC,
+                file,
+                producer.as_ptr() as *const _,
+                producer.len(),
+                0,
+                ptr::null(),
+                0,
+                0,
+                ptr::null(),
+                0,
+                LLVMDWARFEmissionKind::LLVMDWARFEmissionKindFull,
+                0,
+                0,
+                0,
+                ptr::null(),
+                0,
+                ptr::null(),
+                0,
+            );
+            let int_type = LLVMDIBuilderCreateBasicType(
+                builder,
+                c"int".as_ptr(),
+                3,
+                32,
+                DW_ATE_SIGNED,
+                LLVMDIFlagZero,
+            );
+            DebugInfo {
+                builder,
+                context,
+                file,
+                compile_unit,
+                int_type,
+                scopes: Vec::new(),
+                finalized: false,
+            }
+        }
+    }
+
+    pub fn int_type(&self) -> LLVMMetadataRef {
+        self.int_type
+    }
+
+    pub fn array_type(&self, length: u32) -> LLVMMetadataRef {
+        unsafe {
+            let mut subscripts = [LLVMDIBuilderGetOrCreateSubrange(
+                self.builder,
+                0,
+                length as i64,
+            )];
+            LLVMDIBuilderCreateArrayType(
+                self.builder,
+                length as u64 * 32,
+                32,
+                self.int_type,
+                subscripts.as_mut_ptr(),
+                1,
+            )
+        }
+    }
+
+    /// The type of an array parameter, which is passed as a pointer to its
+    /// first element.
+    pub fn array_parameter_type(&self) -> LLVMMetadataRef {
+        let bits = mem::size_of::<usize>() as u64 * 8;
+        unsafe {
+            LLVMDIBuilderCreatePointerType(
+                self.builder,
+                self.int_type,
+                bits,
+                bits as u32,
+                0,
+                ptr::null(),
+                0,
+            )
+        }
+    }
+
+    /// Attaches a subprogram to `function` and makes it the current scope.
+    /// `return_type` is `None` for void functions.
+    pub fn begin_function(
+        &mut self,
+        function: LLVMValueRef,
+        name: &str,
+        return_type: Option<LLVMMetadataRef>,
+        param_types: &[LLVMMetadataRef],
+        line: u32,
+    ) {
+        let mut types = vec![return_type.unwrap_or(ptr::null_mut())];
+        types.extend_from_slice(param_types);
+        unsafe {
+            let subroutine_type = LLVMDIBuilderCreateSubroutineType(
+                self.builder,
+                self.file,
+                types.as_mut_ptr(),
+                types.len() as u32,
+                LLVMDIFlagZero,
+            );
+            let subprogram = LLVMDIBuilderCreateFunction(
+                self.builder,
+                self.file,
+                name.as_ptr() as *const _,
+                name.len(),
+                name.as_ptr() as *const _,
+                name.len(),
+                self.file,
+                line,
+                subroutine_type,
+                0,
+                1,
+                line,
+                LLVMDIFlagPrototyped,
+                0,
+            );
+            LLVMSetSubprogram(function, subprogram);
+            self.scopes = vec![subprogram];
+        }
+    }
+
+    /// Resolves the current function's subprogram, which the verifier
+    /// needs before it accepts the function.
+    pub fn end_function(&mut self) {
+        unsafe { LLVMDIBuilderFinalizeSubprogram(self.builder, self.scopes[0]) }
+    }
+
+    pub fn begin_block(&mut self, line: u32, column: u32) {
+        let block = unsafe {
+            LLVMDIBuilderCreateLexicalBlock(self.builder, self.scope(), self.file, line, column)
+        };
+        self.scopes.push(block);
+    }
+
+    pub fn end_block(&mut self) {
+        self.scopes.pop();
+    }
+
+    /// A location in the innermost scope, for `LLVMSetCurrentDebugLocation2`.
+    pub fn location(&self, line: u32, column: u32) -> LLVMMetadataRef {
+        unsafe {
+            LLVMDIBuilderCreateDebugLocation(
+                self.context,
+                line,
+                column,
+                self.scope(),
+                ptr::null_mut(),
+            )
+        }
+    }
+
+    /// Describes the local variable, or the parameter numbered `argument`
+    /// from 1, that lives in `storage`. The `llvm.dbg.declare` goes at the
+    /// end of `block`, which must not be terminated yet.
+    #[allow(clippy::too_many_arguments)]
+    pub fn declare_variable(
+        &self,
+        storage: LLVMValueRef,
+        name: &str,
+        variable_type: LLVMMetadataRef,
+        argument: Option<u32>,
+        line: u32,
+        column: u32,
+        block: LLVMBasicBlockRef,
+    ) {
+        unsafe {
+            let variable = match argument {
+                Some(number) => LLVMDIBuilderCreateParameterVariable(
+                    self.builder,
+                    self.scope(),
+                    name.as_ptr() as *const _,
+                    name.len(),
+                    number,
+                    self.file,
+                    line,
+                    variable_type,
+                    1,
+                    LLVMDIFlagZero,
+                ),
+                None => LLVMDIBuilderCreateAutoVariable(
+                    self.builder,
+                    self.scope(),
+                    name.as_ptr() as *const _,
+                    name.len(),
+                    self.file,
+                    line,
+                    variable_type,
+                    1,
+                    LLVMDIFlagZero,
+                    0,
+                ),
+            };
+            let expression = LLVMDIBuilderCreateExpression(self.builder, ptr::null_mut(), 0);
+            LLVMDIBuilderInsertDeclareAtEnd(
+                self.builder,
+                storage,
+                variable,
+                expression,
+                self.location(line, column),
+                block,
+            );
+        }
+    }
+
+    pub fn declare_global(
+        &self,
+        global: LLVMValueRef,
+        name: &str,
+        variable_type: LLVMMetadataRef,
+        line: u32,
+    ) {
+        unsafe {
+            let expression = LLVMDIBuilderCreateExpression(self.builder, ptr::null_mut(), 0);
+            let variable = LLVMDIBuilderCreateGlobalVariableExpression(
+                self.builder,
+                self.compile_unit,
+                name.as_ptr() as *const _,
+                name.len(),
+                name.as_ptr() as *const _,
+                name.len(),
+                self.file,
+                line,
+                variable_type,
+                0,
+                expression,
+                ptr::null_mut(),
+                32,
+            );
+            let kind = LLVMGetMDKindIDInContext(self.context, c"dbg".as_ptr(), 3);
+            LLVMGlobalSetMetadata(global, kind, variable);
+        }
+    }
+
+    /// Resolves the descriptors; call once the whole module is generated.
+    pub fn finalize(&mut self) {
+        if !self.finalized {
+            unsafe { LLVMDIBuilderFinalize(self.builder) }
+            self.finalized = true;
+        }
+    }
+
+    fn scope(&self) -> LLVMMetadataRef {
+        *self
+            .scopes
+            .last()
+            .expect("debug locations are only created inside functions")
+    }
+}
+
+impl Drop for DebugInfo {
+    fn drop(&mut self) {
+        // LLVM requires a finalized builder even when codegen gave up early.
+        self.finalize();
+        unsafe { LLVMDisposeDIBuilder(self.builder) }
+    }
+}
@@ -0,0 +1,133 @@
+use crate::span::{SourceFile, Span};
+
+#[derive(PartialEq, Eq, Debug, Copy, Clone)]
+pub enum Severity {
+    Error,
+    // Reserved for lints; nothing reports warnings yet.
+    #[allow(dead_code)]
+    Warning,
+}
+
+/// Stable error codes. The numbers are part of the compiler's interface, so
+/// new codes are only ever appended and existing ones never renumbered.
+#[derive(PartialEq, Eq, Debug, Copy, Clone)]
+pub enum Code {
+    SyntaxError,
+    DuplicateDeclaration,
+    CallOfNonFunction,
+    ArgumentCountMismatch,
+    ArgumentTypeMismatch,
+    UndeclaredIdentifier,
+    NonIntOperand,
+    NonIntCondition,
+    InvalidAssignment,
+    NotAnArray,
+    NonIntIndex,
+    InvalidReturnValue,
+    FunctionUsedAsVariable,
+    MissingReturn,
+    InternalError,
+    UnterminatedComment,
+    NestedComment,
+    InvalidCharacter,
+    LiteralOutOfRange,
+    ArrayUsedAsValue,
+}
+
+impl Code {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Code::SyntaxError => "E0001",
+            Code::DuplicateDeclaration => "E0002",
+            Code::CallOfNonFunction => "E0003",
+            Code::ArgumentCountMismatch => "E0004",
+            Code::ArgumentTypeMismatch => "E0005",
+            Code::UndeclaredIdentifier => "E0006",
+            Code::NonIntOperand => "E0007",
+            Code::NonIntCondition => "E0008",
+            Code::InvalidAssignment => "E0009",
+            Code::NotAnArray => "E0010",
+            Code::NonIntIndex => "E0011",
+            Code::InvalidReturnValue => "E0012",
+            Code::FunctionUsedAsVariable => "E0013",
+            Code::MissingReturn => "E0015",
+            Code::InternalError => "E0016",
+            Code::UnterminatedComment => "E0017",
+            Code::NestedComment => "E0018",
+            Code::InvalidCharacter => "E0019",
+            Code::LiteralOutOfRange => "E0020",
+            Code::ArrayUsedAsValue => "E0021",
+        }
+    }
+}
+
+#[derive(PartialEq, Debug, Clone)]
+pub struct Label {
+    pub span: Span,
+    pub message: String,
+}
+
+#[derive(PartialEq, Debug, Clone)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub code: Code,
+    pub message: String,
+    pub span: Span,
+    pub labels: Vec<Label>,
+    pub notes: Vec<String>,
+}
+
+impl Diagnostic {
+    pub fn error(code: Code, message: &str, span: Span) -> Diagnostic {
+        Diagnostic {
+            severity: Severity::Error,
+            code,
+            message: message.to_string(),
+            span,
+            labels: Vec::new(),
+            notes: Vec::new(),
+        }
+    }
+
+    pub fn with_label(mut self, span: Span, message: &str) -> Diagnostic {
+        self.labels.push(Label {
+            span,
+            message: message.to_string(),
+        });
+        self
+    }
+
+    pub fn with_note(mut self, note: &str) -> Diagnostic {
+        self.notes.push(note.to_string());
+        self
+    }
+
+    /// Renders the diagnostic in the `file:line:col: error[E0003]: message`
+    /// form, with a caret underline under the primary span and each label.
+    pub fn render(&self, source: &SourceFile) -> String {
+        let severity = match self.severity {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+        };
+        let mut output = format!(
+            "{}: {}[{}]: {}\n{}",
+            source.location(self.span),
+            severity,
+            self.code.as_str(),
+            self.message,
+            source.snippet(self.span, '^')
+        );
+        for label in &self.labels {
+            output.push_str(&format!(
+                "\n{}: note: {}\n{}",
+                source.location(label.span),
+                label.message,
+                source.snippet(label.span, '-')
+            ));
+        }
+        for note in &self.notes {
+            output.push_str(&format!("\n  = note: {}", note));
+        }
+        output
+    }
+}
@@ -0,0 +1,310 @@
+use crate::ast::Program;
+use crate::codegen::{codegen, CodegenOptions};
+use crate::emit::{link, OutputKind, TargetMachine, TempDir};
+use crate::generate::generate;
+use crate::interp::{interpret, ErrorKind, Limits};
+use crate::optimize::{optimize, OptLevel};
+use crate::parser::parse_program;
+use crate::printer::print_program;
+use crate::span::SourceFile;
+use crate::typecheck::{typecheck_program, TypecheckProgramResult};
+use regex::Regex;
+use std::fmt::Debug;
+use std::fs;
+use std::io::{Read, Write};
+use std::ops::Range;
+use std::panic::{self, AssertUnwindSafe};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// The levels every program is compiled at. -O0 checks codegen itself and
+/// -O2 what LLVM's passes make of it.
+pub const OPT_LEVELS: [OptLevel; 2] = [OptLevel::O0, OptLevel::O2];
+
+/// How long a compiled program may run, which is far longer than anything
+/// the interpreter finishes within its step limit needs.
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub enum Status {
+    /// The exit code, truncated to a byte the way the operating system does.
+    Exited(u8),
+    Killed(String),
+}
+
+/// What a program observably did: how it ended and everything it printed.
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub struct Trace {
+    pub status: Status,
+    pub output: String,
+}
+
+#[derive(PartialEq, Debug)]
+pub enum Verdict {
+    Agreed,
+    /// The interpreter found no defined behaviour to compare against, such
+    /// as a division by zero, or gave up at a limit.
+    Skipped(String),
+    Diverged(Divergence),
+}
+
+#[derive(PartialEq, Debug)]
+pub struct Divergence {
+    pub name: String,
+    pub opt_level: OptLevel,
+    pub expected: Trace,
+    pub actual: Trace,
+}
+
+impl Divergence {
+    /// Describes the difference: the two statuses if they differ, and the
+    /// first differing line of output with the line before it for context.
+    pub fn render(&self) -> String {
+        let mut report = format!(
+            "{} at -{:?} diverges from the interpreter",
+            self.name, self.opt_level
+        );
+        if self.expected.status != self.actual.status {
+            report.push_str(&format!(
+                "\n  status: interpreter {}, compiled {}",
+                describe(&self.expected.status),
+                describe(&self.actual.status)
+            ));
+        }
+        if self.expected.output != self.actual.output {
+            let expected: Vec<&str> = self.expected.output.lines().collect();
+            let actual: Vec<&str> = self.actual.output.lines().collect();
+            let line = expected
+                .iter()
+                .zip(&actual)
+                .take_while(|(expected, actual)| expected == actual)
+                .count();
+            report.push_str(&format!("\n  output differs at line {}:", line + 1));
+            if line > 0 {
+                report.push_str(&format!("\n      {}", expected[line - 1]));
+            }
+            let missing = "<end of output>";
+            report.push_str(&format!(
+                "\n    - {}\n    + {}",
+                expected.get(line).unwrap_or(&missing),
+                actual.get(line).unwrap_or(&missing)
+            ));
+        }
+        report
+    }
+}
+
+fn describe(status: &Status) -> String {
+    match status {
+        Status::Exited(code) => format!("exited with {}", code),
+        Status::Killed(reason) => reason.clone(),
+    }
+}
+
+/// The totals for a corpus where every program agreed.
+#[derive(PartialEq, Debug, Default)]
+pub struct Summary {
+    pub agreed: usize,
+    /// Each skipped program with the reason.
+    pub skipped: Vec<(String, String)>,
+}
+
+/// Checks every `*.k` program in `directory`, in name order, feeding each
+/// the contents of the `.in` file next to it if there is one. Stops at the
+/// first program that does not compile or diverges, and returns its report.
+pub fn run_corpus(directory: &Path) -> Result<Summary, String> {
+    let entries = fs::read_dir(directory)
+        .map_err(|error| format!("could not read `{}`: {}", directory.display(), error))?;
+    let mut programs: Vec<PathBuf> = entries
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.extension().is_some_and(|extension| extension == "k"))
+        .collect();
+    programs.sort();
+
+    let mut summary = Summary::default();
+    for path in programs {
+        let name = path.display().to_string();
+        let source = fs::read_to_string(&path)
+            .map_err(|error| format!("could not read `{}`: {}", name, error))?;
+        let input = fs::read_to_string(path.with_extension("in")).unwrap_or_default();
+        match compare(&name, &source, &input)? {
+            Verdict::Agreed => summary.agreed += 1,
+            Verdict::Skipped(reason) => summary.skipped.push((name, reason)),
+            Verdict::Diverged(divergence) => return Err(divergence.render()),
+        }
+    }
+    Ok(summary)
+}
+
+/// The `Debug` form of an AST node with every span blanked out, for
+/// comparing trees that were not parsed from the same text.
+pub fn without_spans(node: &impl Debug) -> String {
+    Regex::new(r"\d+\.\.\d+")
+        .unwrap()
+        .replace_all(&format!("{:?}", node), "_")
+        .into_owned()
+}
+
+/// Checks the random program `generate` makes for each seed: its printed
+/// source must parse back to the same tree, spans aside (the generated tree
+/// has none), and then `compare` must find
+/// nothing wrong. A panic anywhere in the compiler is caught and reported,
+/// and every report ends with the seed and the program's source.
+pub fn fuzz(seeds: Range<u64>) -> Result<Summary, String> {
+    let mut summary = Summary::default();
+    for seed in seeds {
+        let (program, input) = generate(seed);
+        let source = print_program(&program);
+        let name = format!("seed {}", seed);
+        let verdict = panic::catch_unwind(AssertUnwindSafe(|| match parse_program(&source) {
+            Ok(parsed) if without_spans(&*parsed) == without_spans(&program) => {
+                compare(&name, &source, &input)
+            }
+            Ok(_) => Err("the printed source parses to a different program".to_string()),
+            Err(_) => Err("the printed source does not parse".to_string()),
+        }))
+        .unwrap_or_else(|panic| {
+            let message = panic
+                .downcast_ref::<String>()
+                .cloned()
+                .or_else(|| {
+                    panic
+                        .downcast_ref::<&str>()
+                        .map(|message| message.to_string())
+                })
+                .unwrap_or_default();
+            Err(format!("the compiler panicked: {}", message))
+        });
+        let report = match verdict {
+            Ok(Verdict::Agreed) => {
+                summary.agreed += 1;
+                continue;
+            }
+            Ok(Verdict::Skipped(reason)) => {
+                summary.skipped.push((name, reason));
+                continue;
+            }
+            Ok(Verdict::Diverged(divergence)) => divergence.render(),
+            Err(message) => format!("{}: {}", name, message),
+        };
+        return Err(format!("{}\n\ninput: {}{}", report, input, source));
+    }
+    Ok(summary)
+}
+
+/// Runs one program through the interpreter and as a native binary at each
+/// of `OPT_LEVELS`, and compares what they did. Programs that fail to
+/// compile are an error.
+pub fn compare(name: &str, source: &str, input: &str) -> Result<Verdict, String> {
+    let file = SourceFile::new(name, source);
+    let program = parse_program(source).map_err(|diagnostics| {
+        diagnostics
+            .iter()
+            .map(|diagnostic| diagnostic.render(&file))
+            .collect::<Vec<_>>()
+            .join("\n")
+    })?;
+    if let TypecheckProgramResult::Failure(diagnostics) = typecheck_program(&program) {
+        return Err(diagnostics
+            .iter()
+            .map(|diagnostic| diagnostic.render(&file))
+            .collect::<Vec<_>>()
+            .join("\n"));
+    }
+    let expected = match reference_trace(&program, input) {
+        Ok(trace) => trace,
+        Err(reason) => return Ok(Verdict::Skipped(reason)),
+    };
+    for opt_level in OPT_LEVELS {
+        let actual = compiled_trace(&program, &file, opt_level, input)?;
+        if actual != expected {
+            return Ok(Verdict::Diverged(Divergence {
+                name: name.to_string(),
+                opt_level,
+                expected,
+                actual,
+            }));
+        }
+    }
+    Ok(Verdict::Agreed)
+}
+
+fn reference_trace(program: &Program, input: &str) -> Result<Trace, String> {
+    let mut output = Vec::new();
+    let status = match interpret(
+        program,
+        Limits::default(),
+        &mut input.as_bytes(),
+        &mut output,
+    ) {
+        Ok(result) => Status::Exited(result as u8),
+        // The runtime's `input()` exits with 1 on malformed input.
+        Err(error) if error.kind == ErrorKind::InvalidInput => Status::Exited(1),
+        Err(error) => return Err(error.message),
+    };
+    Ok(Trace {
+        status,
+        output: String::from_utf8_lossy(&output).into_owned(),
+    })
+}
+
+fn compiled_trace(
+    program: &Program,
+    file: &SourceFile,
+    opt_level: OptLevel,
+    input: &str,
+) -> Result<Trace, String> {
+    let directory = TempDir::new()?;
+    let object = directory.path().join("program.o");
+    let executable = directory.path().join("program");
+
+    let module = codegen(program, file, &CodegenOptions::default())
+        .map_err(|diagnostic| diagnostic.render(file))?;
+    let machine = TargetMachine::host(opt_level)?;
+    optimize(&module, &machine);
+    machine
+        .emit(&module, &object.to_string_lossy(), OutputKind::Object)
+        .and_then(|()| link(&object.to_string_lossy(), &executable.to_string_lossy()))
+        .and_then(|()| execute(&executable, input))
+}
+
+fn execute(executable: &Path, input: &str) -> Result<Trace, String> {
+    let mut child = Command::new(executable)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .map_err(|error| format!("could not run `{}`: {}", executable.display(), error))?;
+    // A program that stops reading early closes the pipe, which is fine.
+    let _ = child.stdin.take().unwrap().write_all(input.as_bytes());
+    let mut stdout = child.stdout.take().unwrap();
+    let reader = thread::spawn(move || {
+        let mut output = Vec::new();
+        let _ = stdout.read_to_end(&mut output);
+        output
+    });
+
+    let start = Instant::now();
+    let status = loop {
+        if let Some(status) = child.try_wait().map_err(|error| error.to_string())? {
+            break Some(status);
+        }
+        if start.elapsed() > TIMEOUT {
+            let _ = child.kill();
+            let _ = child.wait();
+            break None;
+        }
+        thread::sleep(Duration::from_millis(5));
+    };
+    let output = String::from_utf8_lossy(&reader.join().unwrap()).into_owned();
+    let status = match status {
+        Some(status) => match status.code() {
+            Some(code) => Status::Exited(code as u8),
+            None => Status::Killed(format!("killed ({})", status)),
+        },
+        None => Status::Killed(format!("timed out after {}s", TIMEOUT.as_secs())),
+    };
+    Ok(Trace { status, output })
+}
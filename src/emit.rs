@@ -0,0 +1,202 @@
+extern crate llvm_sys as llvm;
+
+use crate::codegen::CodeGen;
+use crate::optimize::OptLevel;
+use crate::runtime;
+use llvm::core::LLVMDisposeMessage;
+use llvm::target::{LLVM_InitializeNativeAsmPrinter, LLVM_InitializeNativeTarget};
+use llvm::target_machine::*;
+use std::env;
+use std::ffi::{CStr, CString};
+use std::fs::{self, DirBuilder};
+use std::io;
+use std::os::raw::c_char;
+use std::os::unix::fs::DirBuilderExt;
+use std::path::{Path, PathBuf};
+use std::process::{self, Command};
+use std::ptr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+#[derive(Copy, Clone)]
+pub enum OutputKind {
+    Object,
+    Assembly,
+}
+
+/// An LLVM target machine for the host, used to turn modules into object or
+/// assembly files without going through `llc`. It is created before the
+/// module is optimized, so that the passes see the target's data layout and
+/// cost model.
+pub struct TargetMachine {
+    machine: LLVMTargetMachineRef,
+    triple: *mut c_char,
+    opt_level: OptLevel,
+}
+
+impl TargetMachine {
+    pub fn host(opt_level: OptLevel) -> Result<TargetMachine, String> {
+        unsafe {
+            if LLVM_InitializeNativeTarget() != 0 || LLVM_InitializeNativeAsmPrinter() != 0 {
+                return Err("the native target is not available in this LLVM build".to_string());
+            }
+            let triple = LLVMGetDefaultTargetTriple();
+            let mut target = ptr::null_mut();
+            let mut error = ptr::null_mut();
+            if LLVMGetTargetFromTriple(triple, &mut target, &mut error) != 0 {
+                let message = take_message(error);
+                LLVMDisposeMessage(triple);
+                return Err(message);
+            }
+            // A generic CPU, so the executables also run on other machines
+            // of the same architecture.
+            let machine = LLVMCreateTargetMachine(
+                target,
+                triple,
+                c"generic".as_ptr(),
+                c"".as_ptr(),
+                opt_level.codegen_level(),
+                // Position independent, since `cc` links PIE executables by
+                // default on most systems.
+                LLVMRelocMode::LLVMRelocPIC,
+                LLVMCodeModel::LLVMCodeModelDefault,
+            );
+            Ok(TargetMachine {
+                machine,
+                triple,
+                opt_level,
+            })
+        }
+    }
+
+    pub fn opt_level(&self) -> OptLevel {
+        self.opt_level
+    }
+
+    pub(crate) fn as_raw(&self) -> LLVMTargetMachineRef {
+        self.machine
+    }
+
+    /// Sets the module's target triple and data layout to the machine's.
+    pub fn configure(&self, codegen: &CodeGen) {
+        let module = codegen.module();
+        unsafe {
+            llvm::core::LLVMSetTarget(module, self.triple);
+            let layout = LLVMCreateTargetDataLayout(self.machine);
+            llvm::target::LLVMSetModuleDataLayout(module, layout);
+            llvm::target::LLVMDisposeTargetData(layout);
+        }
+    }
+
+    pub fn emit(&self, codegen: &CodeGen, path: &str, kind: OutputKind) -> Result<(), String> {
+        let module = codegen.module();
+        let path = CString::new(path).unwrap();
+        let file_type = match kind {
+            OutputKind::Object => LLVMCodeGenFileType::LLVMObjectFile,
+            OutputKind::Assembly => LLVMCodeGenFileType::LLVMAssemblyFile,
+        };
+        self.configure(codegen);
+        unsafe {
+            let mut error = ptr::null_mut();
+            if LLVMTargetMachineEmitToFile(
+                self.machine,
+                module,
+                path.as_ptr() as *mut c_char,
+                file_type,
+                &mut error,
+            ) != 0
+            {
+                return Err(take_message(error));
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Drop for TargetMachine {
+    fn drop(&mut self) {
+        unsafe {
+            LLVMDisposeTargetMachine(self.machine);
+            LLVMDisposeMessage(self.triple);
+        }
+    }
+}
+
+/// Links an object file into an executable with the system C compiler,
+/// together with the runtime that defines the built-in functions and the
+/// bounds check trap. `cc` also pulls in libc, which the runtime calls.
+pub fn link(object: &str, output: &str) -> Result<(), String> {
+    let directory = TempDir::new()?;
+    let runtime = directory.path().join("runtime.o");
+    fs::write(&runtime, runtime::OBJECT).map_err(|error| {
+        format!(
+            "could not write the runtime to `{}`: {}",
+            runtime.display(),
+            error
+        )
+    })?;
+    let result = Command::new("cc")
+        .arg(object)
+        .arg(&runtime)
+        .args(["-o", output])
+        .output()
+        .map_err(|error| format!("could not run the linker `cc`: {}", error))?;
+    if !result.status.success() {
+        return Err(format!(
+            "linking with `cc` failed ({})\n{}",
+            result.status,
+            String::from_utf8_lossy(&result.stderr).trim_end()
+        ));
+    }
+    Ok(())
+}
+
+/// A directory only this process can use, for the intermediate files of a
+/// build, removed with everything in it when dropped. Creating a directory
+/// fails if it already exists, so no other process can have put files there.
+pub struct TempDir {
+    path: PathBuf,
+}
+
+impl TempDir {
+    pub fn new() -> Result<TempDir, String> {
+        static DIRECTORIES: AtomicUsize = AtomicUsize::new(0);
+        loop {
+            let path = env::temp_dir().join(format!(
+                "project-{}-{}-{}",
+                process::id(),
+                DIRECTORIES.fetch_add(1, Ordering::Relaxed),
+                SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .map_or(0, |time| time.subsec_nanos())
+            ));
+            match DirBuilder::new().mode(0o700).create(&path) {
+                Ok(()) => return Ok(TempDir { path }),
+                Err(error) if error.kind() == io::ErrorKind::AlreadyExists => continue,
+                Err(error) => {
+                    return Err(format!(
+                        "could not create a directory in `{}`: {}",
+                        env::temp_dir().display(),
+                        error
+                    ))
+                }
+            }
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.path);
+    }
+}
+
+pub(crate) unsafe fn take_message(message: *mut c_char) -> String {
+    let text = CStr::from_ptr(message).to_string_lossy().into_owned();
+    LLVMDisposeMessage(message);
+    text
+}
@@ -0,0 +1,532 @@
+use crate::ast::{
+    CompoundStatement, Expression, FunctionCall, FunctionDeclaration, IdentifierType, IfStatement,
+    Operator, Param, Program, Statement, Var, VarDeclaration, WhileStatement,
+};
+use crate::span::Span;
+
+const MAX_FUNCTIONS: usize = 4;
+const MAX_PARAMS: usize = 3;
+const MAX_STATEMENTS: usize = 5;
+const MAX_NESTING: usize = 3;
+const MAX_EXPRESSION_DEPTH: usize = 3;
+const MAX_ITERATIONS: i32 = 3;
+/// The largest `d` that `main` passes; every call below it passes `d - 1`.
+const MAX_CALL_DEPTH: i32 = 3;
+/// Call sites per function, which with the above bounds the total work.
+const MAX_CALLS: usize = 2;
+const INPUT_VALUES: usize = 8;
+
+/// xorshift64*: reproducible from a seed, and good enough to pick shapes.
+pub struct Rng {
+    state: u64,
+}
+
+impl Rng {
+    pub fn new(seed: u64) -> Rng {
+        // One splitmix64 step, so neighbouring seeds start far apart and the
+        // state is never the zero that xorshift cannot leave.
+        let mut z = seed.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        Rng {
+            state: (z ^ (z >> 31)) | 1,
+        }
+    }
+
+    pub fn next(&mut self) -> u64 {
+        self.state ^= self.state >> 12;
+        self.state ^= self.state << 25;
+        self.state ^= self.state >> 27;
+        self.state.wrapping_mul(0x2545_f491_4f6c_dd1d)
+    }
+
+    /// A number in `0..n`.
+    pub fn below(&mut self, n: usize) -> usize {
+        (self.next() % n as u64) as usize
+    }
+
+    pub fn chance(&mut self, percent: u64) -> bool {
+        self.next() % 100 < percent
+    }
+
+    fn pick<'a, T>(&mut self, items: &'a [T]) -> &'a T {
+        &items[self.below(items.len())]
+    }
+}
+
+/// Generates a random program that passes `typecheck_program`, together with
+/// the standard input to run it with.
+///
+/// The programs have defined behaviour, so the interpreter and compiled code
+/// must agree on them: every variable is assigned before it is read, array
+/// indices are reduced into range, divisors are kept positive, and loops and
+/// recursion are bounded. Each function takes a depth `d` first and returns
+/// straight away when it reaches 0, and every call passes `d - 1`.
+pub fn generate(seed: u64) -> (Program, String) {
+    let mut rng = Rng::new(seed);
+    let length = 1 + rng.below(6) as i32;
+    let mut generator = Generator {
+        rng,
+        length,
+        functions: Vec::new(),
+        scalars: Vec::new(),
+        writable: Vec::new(),
+        arrays: Vec::new(),
+        counters: Vec::new(),
+        in_main: false,
+        returns_int: true,
+        calls: 0,
+        names: 0,
+    };
+    let program = generator.program();
+    let input: Vec<String> = (0..INPUT_VALUES)
+        .map(|_| (generator.rng.below(201) as i32 - 100).to_string())
+        .collect();
+    (program, input.join(" ") + "\n")
+}
+
+struct Signature {
+    name: String,
+    returns_int: bool,
+    /// Whether each parameter after `d` is an array.
+    arrays: Vec<bool>,
+}
+
+struct Generator {
+    rng: Rng,
+    /// The length of every array, so that any array can be passed for any
+    /// array parameter and indexed the same way.
+    length: i32,
+    functions: Vec<Signature>,
+    // What is in scope in the function being generated. Every name in the
+    // program is distinct, so nothing is ever shadowed.
+    scalars: Vec<String>,
+    // The scalars statements may assign: all but `d` and loop counters.
+    writable: Vec<String>,
+    arrays: Vec<String>,
+    // The loop counters each open block has to declare, innermost last.
+    counters: Vec<Vec<String>>,
+    in_main: bool,
+    returns_int: bool,
+    // Call sites generated in the current function.
+    calls: usize,
+    names: usize,
+}
+
+impl Generator {
+    fn name(&mut self, prefix: &str) -> String {
+        self.names += 1;
+        format!("{}{}", prefix, self.names)
+    }
+
+    fn program(&mut self) -> Program {
+        let mut var_declarations = Vec::new();
+        for _ in 0..1 + self.rng.below(3) {
+            let name = self.name("g");
+            self.scalars.push(name.clone());
+            self.writable.push(name.clone());
+            var_declarations.push(VarDeclaration::VarDeclaration(
+                IdentifierType::Int,
+                name,
+                Span::default(),
+            ));
+        }
+        // At least one, so there is always an array to pass.
+        for _ in 0..1 + self.rng.below(2) {
+            let name = self.name("a");
+            self.arrays.push(name.clone());
+            var_declarations.push(VarDeclaration::ArrDeclaration(
+                IdentifierType::Int,
+                name,
+                self.length,
+                Span::default(),
+            ));
+        }
+
+        for _ in 0..self.rng.below(MAX_FUNCTIONS + 1) {
+            let name = self.name("f");
+            let returns_int = self.rng.chance(70);
+            let arrays = (0..self.rng.below(MAX_PARAMS + 1))
+                .map(|_| self.rng.chance(30))
+                .collect();
+            self.functions.push(Signature {
+                name,
+                returns_int,
+                arrays,
+            });
+        }
+        let mut fun_declarations: Vec<FunctionDeclaration> = (0..self.functions.len())
+            .map(|index| self.function(index))
+            .collect();
+        fun_declarations.push(self.main());
+        Program {
+            var_declarations,
+            fun_declarations,
+        }
+    }
+
+    fn function(&mut self, index: usize) -> FunctionDeclaration {
+        let (scalars, writable, arrays) = self.scope();
+        self.in_main = false;
+        self.returns_int = self.functions[index].returns_int;
+        self.calls = 0;
+        let mut params = vec![Param::Var(
+            IdentifierType::Int,
+            "d".to_string(),
+            Span::default(),
+        )];
+        self.scalars.push("d".to_string());
+        for is_array in self.functions[index].arrays.clone() {
+            let name = self.name("p");
+            if is_array {
+                self.arrays.push(name.clone());
+                params.push(Param::ArrVar(IdentifierType::Int, name, Span::default()));
+            } else {
+                self.scalars.push(name.clone());
+                self.writable.push(name.clone());
+                params.push(Param::Var(IdentifierType::Int, name, Span::default()));
+            }
+        }
+        let returns_int = self.functions[index].returns_int;
+        // Calls here would recurse without end, so the guard makes none.
+        self.calls = MAX_CALLS;
+        let guard_value = returns_int.then(|| Box::new(self.expression(1)));
+        self.calls = 0;
+        let guard = Statement::IfStatement(Box::new(IfStatement::IfStmt(
+            Box::new(operation(variable("d"), Operator::Le, literal(0))),
+            Box::new(Statement::ReturnStatement(guard_value, Span::default())),
+            Span::default(),
+        )));
+        let body = self.block(0, vec![guard], returns_int);
+        self.restore((scalars, writable, arrays));
+        FunctionDeclaration {
+            return_type: if returns_int {
+                IdentifierType::Int
+            } else {
+                IdentifierType::Void
+            },
+            function_name: self.functions[index].name.clone(),
+            params,
+            body: Box::new(body),
+            span: Span::default(),
+        }
+    }
+
+    fn main(&mut self) -> FunctionDeclaration {
+        self.in_main = true;
+        self.returns_int = true;
+        self.calls = 0;
+        let body = self.block(0, Vec::new(), true);
+        FunctionDeclaration {
+            return_type: IdentifierType::Int,
+            function_name: "main".to_string(),
+            params: Vec::new(),
+            body: Box::new(body),
+            span: Span::default(),
+        }
+    }
+
+    fn scope(&self) -> (usize, usize, usize) {
+        (self.scalars.len(), self.writable.len(), self.arrays.len())
+    }
+
+    fn restore(&mut self, (scalars, writable, arrays): (usize, usize, usize)) {
+        self.scalars.truncate(scalars);
+        self.writable.truncate(writable);
+        self.arrays.truncate(arrays);
+    }
+
+    /// A block that starts with `prologue`, then assigns a few fresh locals,
+    /// and has random statements and a final `return` if `returns_int`.
+    fn block(
+        &mut self,
+        nesting: usize,
+        prologue: Vec<Statement>,
+        returns_int: bool,
+    ) -> CompoundStatement {
+        let saved = self.scope();
+        self.counters.push(Vec::new());
+        let mut declarations = Vec::new();
+        let mut statements = prologue;
+        let locals = if nesting == 0 {
+            self.rng.below(3)
+        } else {
+            self.rng.below(4) / 3
+        };
+        for _ in 0..locals {
+            let name = self.name("v");
+            let value = self.expression(1);
+            statements.push(assign(Var::Var(name.clone(), Span::default()), value));
+            declarations.push(VarDeclaration::VarDeclaration(
+                IdentifierType::Int,
+                name.clone(),
+                Span::default(),
+            ));
+            self.scalars.push(name.clone());
+            self.writable.push(name);
+        }
+        if nesting == 0 && self.rng.chance(40) {
+            let name = self.name("c");
+            for index in 0..self.length {
+                let value = self.expression(1);
+                let element =
+                    Var::ArrayAccess(name.clone(), Box::new(literal(index)), Span::default());
+                statements.push(assign(element, value));
+            }
+            declarations.push(VarDeclaration::ArrDeclaration(
+                IdentifierType::Int,
+                name.clone(),
+                self.length,
+                Span::default(),
+            ));
+            self.arrays.push(name);
+        }
+        for _ in 0..1 + self.rng.below(MAX_STATEMENTS) {
+            statements.extend(self.statement(nesting));
+        }
+        if returns_int {
+            let value = self.expression(MAX_EXPRESSION_DEPTH);
+            statements.push(Statement::ReturnStatement(
+                Some(Box::new(value)),
+                Span::default(),
+            ));
+        }
+        for counter in self.counters.pop().unwrap() {
+            declarations.push(VarDeclaration::VarDeclaration(
+                IdentifierType::Int,
+                counter,
+                Span::default(),
+            ));
+        }
+        self.restore(saved);
+        CompoundStatement {
+            declarations,
+            statements: statements.into_iter().map(Box::new).collect(),
+            span: Span::default(),
+        }
+    }
+
+    fn statement(&mut self, nesting: usize) -> Vec<Statement> {
+        let nested = nesting < MAX_NESTING;
+        match self.rng.below(20) {
+            0..=3 if !self.writable.is_empty() => {
+                let target = Var::Var(self.rng.pick(&self.writable).clone(), Span::default());
+                let value = self.expression(MAX_EXPRESSION_DEPTH);
+                vec![assign(target, value)]
+            }
+            4..=6 => {
+                let target = self.element(1);
+                let value = self.expression(MAX_EXPRESSION_DEPTH);
+                vec![assign(target, value)]
+            }
+            7..=9 => {
+                let value = self.expression(MAX_EXPRESSION_DEPTH);
+                vec![expression_statement(call("output", vec![value]))]
+            }
+            10..=12 if nested => vec![self.if_statement(nesting)],
+            13..=15 if nested => self.while_statement(nesting),
+            16 | 17 if self.calls < MAX_CALLS && !self.functions.is_empty() => {
+                let index = self.rng.below(self.functions.len());
+                vec![expression_statement(self.call_function(index))]
+            }
+            18 if nesting > 0 => {
+                let value = self
+                    .returns_int
+                    .then(|| Box::new(self.expression(MAX_EXPRESSION_DEPTH)));
+                vec![Statement::ReturnStatement(value, Span::default())]
+            }
+            19 => vec![Statement::EmptyStatement(Span::default())],
+            _ => {
+                let value = self.expression(MAX_EXPRESSION_DEPTH);
+                vec![expression_statement(call("output", vec![value]))]
+            }
+        }
+    }
+
+    fn if_statement(&mut self, nesting: usize) -> Statement {
+        let condition = Box::new(self.expression(MAX_EXPRESSION_DEPTH));
+        let then_block = self.nested_block(nesting);
+        let if_statement = if self.rng.chance(50) {
+            // Sometimes an `else if` chain rather than a block.
+            let else_statement = if nesting + 1 < MAX_NESTING && self.rng.chance(30) {
+                self.if_statement(nesting + 1)
+            } else {
+                self.nested_block(nesting)
+            };
+            IfStatement::IfElseStmt(
+                condition,
+                Box::new(then_block),
+                Box::new(else_statement),
+                Span::default(),
+            )
+        } else {
+            IfStatement::IfStmt(condition, Box::new(then_block), Span::default())
+        };
+        Statement::IfStatement(Box::new(if_statement))
+    }
+
+    /// `i = 0; while (i < n) { ...; i = i + 1; }` with a fresh counter `i`
+    /// that nothing else assigns.
+    fn while_statement(&mut self, nesting: usize) -> Vec<Statement> {
+        let counter = self.name("i");
+        self.counters.last_mut().unwrap().push(counter.clone());
+        let iterations = 1 + self.rng.below(MAX_ITERATIONS as usize) as i32;
+        let init = assign(Var::Var(counter.clone(), Span::default()), literal(0));
+        self.scalars.push(counter.clone());
+        let mut body = self.block(nesting + 1, Vec::new(), false);
+        let next = operation(variable(&counter), Operator::Add, literal(1));
+        body.statements.push(Box::new(assign(
+            Var::Var(counter.clone(), Span::default()),
+            next,
+        )));
+        let condition = operation(variable(&counter), Operator::Lt, literal(iterations));
+        let while_statement = Statement::WhileStatement(Box::new(WhileStatement {
+            condition: Box::new(condition),
+            statement: Box::new(Statement::CompoundStatement(Box::new(body))),
+            span: Span::default(),
+        }));
+        vec![init, while_statement]
+    }
+
+    fn nested_block(&mut self, nesting: usize) -> Statement {
+        Statement::CompoundStatement(Box::new(self.block(nesting + 1, Vec::new(), false)))
+    }
+
+    /// A call to function `index` with `d - 1` for the depth, or a constant
+    /// from `main`.
+    fn call_function(&mut self, index: usize) -> Expression {
+        self.calls += 1;
+        let depth = if self.in_main {
+            literal(self.rng.below(MAX_CALL_DEPTH as usize + 1) as i32)
+        } else {
+            operation(variable("d"), Operator::Sub, literal(1))
+        };
+        let mut args = vec![depth];
+        for is_array in self.functions[index].arrays.clone() {
+            if is_array {
+                let name = self.rng.pick(&self.arrays).clone();
+                args.push(variable(&name));
+            } else {
+                args.push(self.expression(1));
+            }
+        }
+        call(&self.functions[index].name.clone(), args)
+    }
+
+    /// An element of some array in scope, at an index reduced into range
+    /// with `((e % n) + n) % n`.
+    fn element(&mut self, depth: usize) -> Var {
+        let name = self.rng.pick(&self.arrays).clone();
+        let index = self.expression(depth);
+        let length = self.length;
+        let index = operation(
+            operation(
+                operation(index, Operator::Mod, literal(length)),
+                Operator::Add,
+                literal(length),
+            ),
+            Operator::Mod,
+            literal(length),
+        );
+        Var::ArrayAccess(name, Box::new(index), Span::default())
+    }
+
+    fn expression(&mut self, depth: usize) -> Expression {
+        if depth == 0 {
+            return self.leaf();
+        }
+        match self.rng.below(12) {
+            0..=4 => {
+                let op = match self.rng.below(11) {
+                    0 => Operator::Mul,
+                    1 => Operator::Div,
+                    2 => Operator::Mod,
+                    3 | 4 => Operator::Add,
+                    5 | 6 => Operator::Sub,
+                    7 => Operator::Lt,
+                    8 => Operator::Le,
+                    9 => Operator::Eq,
+                    _ => match self.rng.below(3) {
+                        0 => Operator::Gt,
+                        1 => Operator::Ge,
+                        _ => Operator::Ne,
+                    },
+                };
+                let lhs = self.expression(depth - 1);
+                let mut rhs = self.expression(depth - 1);
+                if matches!(op, Operator::Div | Operator::Mod) {
+                    // Between 2 and 14, so neither zero nor -1.
+                    rhs = operation(
+                        operation(rhs, Operator::Mod, literal(7)),
+                        Operator::Add,
+                        literal(8),
+                    );
+                }
+                operation(lhs, op, rhs)
+            }
+            5 if self.calls < MAX_CALLS => {
+                let int_functions: Vec<usize> = (0..self.functions.len())
+                    .filter(|&index| self.functions[index].returns_int)
+                    .collect();
+                if int_functions.is_empty() {
+                    return self.leaf();
+                }
+                let index = *self.rng.pick(&int_functions);
+                self.call_function(index)
+            }
+            6 if !self.writable.is_empty() && self.rng.chance(30) => {
+                let target = Var::Var(self.rng.pick(&self.writable).clone(), Span::default());
+                let value = self.expression(depth - 1);
+                Expression::Assignment(Box::new(target), Box::new(value), Span::default())
+            }
+            7 if self.rng.chance(20) => call("input", Vec::new()),
+            _ => self.leaf(),
+        }
+    }
+
+    fn leaf(&mut self) -> Expression {
+        match self.rng.below(10) {
+            0..=3 => literal(match self.rng.below(20) {
+                0 => i32::MAX,
+                1 => self.rng.below(1 << 31) as i32,
+                2 | 3 => self.rng.below(1000) as i32,
+                _ => self.rng.below(11) as i32,
+            }),
+            4..=7 if !self.scalars.is_empty() => variable(&self.rng.pick(&self.scalars).clone()),
+            8 | 9 => Expression::Var(Box::new(self.element(0))),
+            _ => literal(self.rng.below(11) as i32),
+        }
+    }
+}
+
+fn literal(value: i32) -> Expression {
+    Expression::IntegerLiteral(value, Span::default())
+}
+
+fn variable(name: &str) -> Expression {
+    Expression::Var(Box::new(Var::Var(name.to_string(), Span::default())))
+}
+
+fn operation(lhs: Expression, op: Operator, rhs: Expression) -> Expression {
+    Expression::Operation(Box::new(lhs), op, Box::new(rhs), Span::default())
+}
+
+fn call(name: &str, args: Vec<Expression>) -> Expression {
+    Expression::Call(Box::new(FunctionCall {
+        name: name.to_string(),
+        args: args.into_iter().map(Box::new).collect(),
+        span: Span::default(),
+    }))
+}
+
+fn assign(target: Var, value: Expression) -> Statement {
+    expression_statement(Expression::Assignment(
+        Box::new(target),
+        Box::new(value),
+        Span::default(),
+    ))
+}
+
+fn expression_statement(expression: Expression) -> Statement {
+    Statement::Expression(Box::new(expression))
+}
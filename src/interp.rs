@@ -0,0 +1,478 @@
+use crate::ast::{
+    CompoundStatement, Expression, FunctionCall, FunctionDeclaration, IdentifierType, IfStatement,
+    Operator, Param, Program, Statement, Var, VarDeclaration,
+};
+use crate::runtime::read_int;
+use crate::span::{SourceFile, Span};
+use std::collections::HashMap;
+use std::io::{BufRead, Write};
+use std::mem;
+use std::thread;
+
+/// Host stack reserved for each interpreted call, so `max_depth` is reached
+/// before the interpreter's own recursion overflows. Unoptimized builds use
+/// about 5 KiB a call plus more for nested statements.
+const STACK_PER_CALL: usize = 64 * 1024;
+
+/// How much work a program may do before the interpreter gives up on it.
+#[derive(PartialEq, Debug, Copy, Clone)]
+pub struct Limits {
+    /// Statements executed plus expressions evaluated.
+    pub max_steps: u64,
+    /// Calls that may be active at once, counting `main`.
+    pub max_depth: usize,
+}
+
+impl Default for Limits {
+    fn default() -> Limits {
+        Limits {
+            max_steps: 100_000_000,
+            max_depth: 1_000,
+        }
+    }
+}
+
+#[derive(PartialEq, Eq, Debug, Copy, Clone)]
+pub enum ErrorKind {
+    StepLimit,
+    DepthLimit,
+    DivisionByZero,
+    /// `INT_MIN / -1` and `INT_MIN % -1`, which trap on x86.
+    Overflow,
+    IndexOutOfBounds,
+    InvalidInput,
+    Output,
+    /// There is no `main`, or it takes parameters.
+    InvalidMain,
+    /// The program breaks a rule that typecheck enforces, such as using an
+    /// array as an int.
+    Internal,
+}
+
+/// Why a program stopped before `main` returned.
+#[derive(PartialEq, Debug, Clone)]
+pub struct RuntimeError {
+    pub kind: ErrorKind,
+    pub message: String,
+    pub span: Span,
+}
+
+impl RuntimeError {
+    fn new(kind: ErrorKind, message: String, span: Span) -> RuntimeError {
+        RuntimeError {
+            kind,
+            message,
+            span,
+        }
+    }
+
+    /// Renders the error as `file:line:col: runtime error: message`, with
+    /// the offending source line underneath when there is one.
+    pub fn render(&self, source: &SourceFile) -> String {
+        let mut output = format!(
+            "{}: runtime error: {}",
+            source.location(self.span),
+            self.message
+        );
+        if self.span.end > self.span.start {
+            output.push('\n');
+            output.push_str(&source.snippet(self.span, '^'));
+        }
+        output
+    }
+}
+
+/// Runs a type-checked program from its `main` by walking the AST, and
+/// returns what `main` returns (0 for a void `main`). `input()` reads from
+/// `input` and `output()` writes to `output`.
+///
+/// This is the reference semantics of the language: ints wrap on overflow,
+/// and variables start out as 0. Whatever the compiled code leaves undefined
+/// (division by zero, an index out of range, including through an array
+/// parameter) is an error here.
+pub fn interpret(
+    program: &Program,
+    limits: Limits,
+    input: &mut (dyn BufRead + Send),
+    output: &mut (dyn Write + Send),
+) -> Result<i32, RuntimeError> {
+    thread::scope(|scope| {
+        thread::Builder::new()
+            .name("interpreter".to_string())
+            .stack_size((limits.max_depth + 16) * STACK_PER_CALL)
+            .spawn_scoped(scope, || run(program, limits, input, output))
+            .expect("could not start the interpreter thread")
+            .join()
+            .unwrap_or_else(|panic| std::panic::resume_unwind(panic))
+    })
+}
+
+fn run(
+    program: &Program,
+    limits: Limits,
+    input: &mut dyn BufRead,
+    output: &mut dyn Write,
+) -> Result<i32, RuntimeError> {
+    let mut interpreter = Interpreter {
+        functions: HashMap::new(),
+        globals: HashMap::new(),
+        scopes: Vec::new(),
+        memory: Vec::new(),
+        limits,
+        steps: 0,
+        depth: 0,
+        input,
+        output,
+    };
+    for declaration in &program.var_declarations {
+        let (name, slot) = interpreter.allocate(declaration);
+        interpreter.globals.insert(name, slot);
+    }
+    for function in &program.fun_declarations {
+        interpreter
+            .functions
+            .insert(function.function_name.as_str(), function);
+    }
+    let main = match interpreter.functions.get("main") {
+        Some(main) => *main,
+        None => {
+            return Err(RuntimeError::new(
+                ErrorKind::InvalidMain,
+                "the program has no `main` function".to_string(),
+                Span::default(),
+            ))
+        }
+    };
+    if !main.params.is_empty() {
+        return Err(RuntimeError::new(
+            ErrorKind::InvalidMain,
+            "`main` must not take parameters to be run".to_string(),
+            main.span,
+        ));
+    }
+    Ok(interpreter
+        .call_function(main, Vec::new(), main.span)?
+        .unwrap_or(0))
+}
+
+/// Where a variable lives in `Interpreter::memory`.
+#[derive(Copy, Clone)]
+enum Slot {
+    Scalar(usize),
+    /// The first element and the length.
+    Array(usize, usize),
+}
+
+enum Argument {
+    Int(i32),
+    Array(usize, usize),
+}
+
+enum Flow {
+    Next,
+    Return(Option<i32>),
+}
+
+struct Interpreter<'a, 'io> {
+    functions: HashMap<&'a str, &'a FunctionDeclaration>,
+    globals: HashMap<&'a str, Slot>,
+    // The running function's scopes, innermost last.
+    scopes: Vec<HashMap<&'a str, Slot>>,
+    // Every int cell: globals first, then each active call's locals, so a
+    // call or block frees its own by truncating.
+    memory: Vec<i32>,
+    limits: Limits,
+    steps: u64,
+    depth: usize,
+    input: &'io mut dyn BufRead,
+    output: &'io mut dyn Write,
+}
+
+impl<'a, 'io> Interpreter<'a, 'io> {
+    fn allocate(&mut self, declaration: &'a VarDeclaration) -> (&'a str, Slot) {
+        let start = self.memory.len();
+        match declaration {
+            VarDeclaration::VarDeclaration(_, name, _) => {
+                self.memory.push(0);
+                (name, Slot::Scalar(start))
+            }
+            VarDeclaration::ArrDeclaration(_, name, size, _) => {
+                let length = *size as usize;
+                self.memory.resize(start + length, 0);
+                (name, Slot::Array(start, length))
+            }
+        }
+    }
+
+    fn lookup(&self, name: &str) -> Slot {
+        self.scopes
+            .iter()
+            .rev()
+            .find_map(|scope| scope.get(name))
+            .or_else(|| self.globals.get(name))
+            .copied()
+            .unwrap_or_else(|| unreachable!("typecheck rejects undeclared `{}`", name))
+    }
+
+    fn step(&mut self, span: Span) -> Result<(), RuntimeError> {
+        self.steps += 1;
+        if self.steps > self.limits.max_steps {
+            return Err(RuntimeError::new(
+                ErrorKind::StepLimit,
+                format!(
+                    "the program did not finish within {} steps",
+                    self.limits.max_steps
+                ),
+                span,
+            ));
+        }
+        Ok(())
+    }
+
+    fn call_function(
+        &mut self,
+        function: &'a FunctionDeclaration,
+        arguments: Vec<Argument>,
+        span: Span,
+    ) -> Result<Option<i32>, RuntimeError> {
+        if self.depth == self.limits.max_depth {
+            return Err(RuntimeError::new(
+                ErrorKind::DepthLimit,
+                format!(
+                    "calls nested more than {} deep calling `{}`",
+                    self.limits.max_depth, function.function_name
+                ),
+                span,
+            ));
+        }
+        let caller_scopes = mem::replace(&mut self.scopes, vec![HashMap::new()]);
+        let caller_memory = self.memory.len();
+        for (param, argument) in function.params.iter().zip(arguments) {
+            let slot = match (param, argument) {
+                (Param::Var(_, name, _), Argument::Int(value)) => {
+                    self.memory.push(value);
+                    (name, Slot::Scalar(self.memory.len() - 1))
+                }
+                (Param::ArrVar(_, name, _), Argument::Array(start, length)) => {
+                    (name, Slot::Array(start, length))
+                }
+                _ => unreachable!("typecheck matches arguments to parameters"),
+            };
+            self.scopes[0].insert(slot.0, slot.1);
+        }
+        self.depth += 1;
+        let flow = self.execute_compound(&function.body);
+        self.depth -= 1;
+        self.memory.truncate(caller_memory);
+        self.scopes = caller_scopes;
+        match flow? {
+            Flow::Return(value) => Ok(value),
+            Flow::Next => match function.return_type {
+                IdentifierType::Void => Ok(None),
+                // Typecheck rejects int functions that can fall off the end.
+                IdentifierType::Int => unreachable!(
+                    "`{}` ended without returning a value",
+                    function.function_name
+                ),
+            },
+        }
+    }
+
+    fn call(&mut self, call: &'a FunctionCall) -> Result<Option<i32>, RuntimeError> {
+        let function = self.functions.get(call.name.as_str()).copied();
+        let mut arguments = Vec::new();
+        for (i, arg) in call.args.iter().enumerate() {
+            let is_array_param = matches!(
+                function.and_then(|function| function.params.get(i)),
+                Some(Param::ArrVar(_, _, _))
+            );
+            arguments.push(match &**arg {
+                Expression::Var(var) if is_array_param => match &**var {
+                    Var::Var(name, _) => match self.lookup(name) {
+                        Slot::Array(start, length) => Argument::Array(start, length),
+                        Slot::Scalar(_) => unreachable!("typecheck only passes arrays here"),
+                    },
+                    Var::ArrayAccess(_, _, _) => {
+                        unreachable!("typecheck only passes whole arrays to array parameters")
+                    }
+                },
+                _ => Argument::Int(self.evaluate(arg)?),
+            });
+        }
+        if let Some(function) = function {
+            return self.call_function(function, arguments, call.span);
+        }
+        match (call.name.as_str(), arguments.as_slice()) {
+            ("input", []) => match read_int(self.input) {
+                Some(value) => Ok(Some(value)),
+                None => Err(RuntimeError::new(
+                    ErrorKind::InvalidInput,
+                    "input: expected an integer".to_string(),
+                    call.span,
+                )),
+            },
+            ("output", [Argument::Int(value)]) => {
+                writeln!(self.output, "{}", value).map_err(|error| {
+                    RuntimeError::new(
+                        ErrorKind::Output,
+                        format!("could not write the output: {}", error),
+                        call.span,
+                    )
+                })?;
+                Ok(None)
+            }
+            _ => unreachable!("typecheck rejects calls to undeclared `{}`", call.name),
+        }
+    }
+
+    fn execute_compound(&mut self, statement: &'a CompoundStatement) -> Result<Flow, RuntimeError> {
+        let memory = self.memory.len();
+        let mut scope = HashMap::new();
+        for declaration in &statement.declarations {
+            let (name, slot) = self.allocate(declaration);
+            scope.insert(name, slot);
+        }
+        self.scopes.push(scope);
+        let mut flow = Ok(Flow::Next);
+        for statement in &statement.statements {
+            flow = self.execute(statement);
+            if !matches!(flow, Ok(Flow::Next)) {
+                break;
+            }
+        }
+        self.scopes.pop();
+        self.memory.truncate(memory);
+        flow
+    }
+
+    fn execute(&mut self, statement: &'a Statement) -> Result<Flow, RuntimeError> {
+        self.step(statement.span())?;
+        match statement {
+            Statement::Expression(expression) => {
+                self.evaluate(expression)?;
+                Ok(Flow::Next)
+            }
+            Statement::CompoundStatement(compound_statement) => {
+                self.execute_compound(compound_statement)
+            }
+            Statement::IfStatement(if_statement) => match &**if_statement {
+                IfStatement::IfStmt(condition, statement, _) => {
+                    if self.evaluate(condition)? != 0 {
+                        return self.execute(statement);
+                    }
+                    Ok(Flow::Next)
+                }
+                IfStatement::IfElseStmt(condition, then_statement, else_statement, _) => {
+                    if self.evaluate(condition)? != 0 {
+                        self.execute(then_statement)
+                    } else {
+                        self.execute(else_statement)
+                    }
+                }
+            },
+            Statement::WhileStatement(while_statement) => {
+                while self.evaluate(&while_statement.condition)? != 0 {
+                    if let Flow::Return(value) = self.execute(&while_statement.statement)? {
+                        return Ok(Flow::Return(value));
+                    }
+                }
+                Ok(Flow::Next)
+            }
+            Statement::ReturnStatement(value, _) => {
+                let value = match value {
+                    Some(expression) => Some(self.evaluate(expression)?),
+                    None => None,
+                };
+                Ok(Flow::Return(value))
+            }
+            Statement::EmptyStatement(_) => Ok(Flow::Next),
+        }
+    }
+
+    fn evaluate(&mut self, expression: &'a Expression) -> Result<i32, RuntimeError> {
+        self.step(expression.span())?;
+        match expression {
+            Expression::IntegerLiteral(value, _) => Ok(*value),
+            Expression::Operation(lhs, op, rhs, span) => {
+                let lhs = self.evaluate(lhs)?;
+                let rhs = self.evaluate(rhs)?;
+                arithmetic(lhs, op, rhs, *span)
+            }
+            // The value is evaluated before the index, as in the compiled code.
+            Expression::Assignment(var, value, _) => {
+                let value = self.evaluate(value)?;
+                let address = self.address(var)?;
+                self.memory[address] = value;
+                Ok(value)
+            }
+            Expression::Var(var) => {
+                let address = self.address(var)?;
+                Ok(self.memory[address])
+            }
+            // A void call only appears as a statement, where the value is unused.
+            Expression::Call(call) => Ok(self.call(call)?.unwrap_or(0)),
+        }
+    }
+
+    fn address(&mut self, var: &'a Var) -> Result<usize, RuntimeError> {
+        match var {
+            Var::Var(name, _) => match self.lookup(name) {
+                Slot::Scalar(address) => Ok(address),
+                Slot::Array(_, _) => Err(RuntimeError::new(
+                    ErrorKind::Internal,
+                    format!("internal error: array `{}` used as an int", name),
+                    var.span(),
+                )),
+            },
+            Var::ArrayAccess(name, index, span) => {
+                let index = self.evaluate(index)?;
+                match self.lookup(name) {
+                    Slot::Array(start, length) if index >= 0 && (index as usize) < length => {
+                        Ok(start + index as usize)
+                    }
+                    Slot::Array(_, length) => Err(RuntimeError::new(
+                        ErrorKind::IndexOutOfBounds,
+                        format!(
+                            "index {} out of bounds for array `{}` of length {}",
+                            index, name, length
+                        ),
+                        *span,
+                    )),
+                    Slot::Scalar(_) => unreachable!("typecheck rejects indexing an int"),
+                }
+            }
+        }
+    }
+}
+
+fn arithmetic(lhs: i32, op: &Operator, rhs: i32, span: Span) -> Result<i32, RuntimeError> {
+    let value = match op {
+        Operator::Add => lhs.wrapping_add(rhs),
+        Operator::Sub => lhs.wrapping_sub(rhs),
+        Operator::Mul => lhs.wrapping_mul(rhs),
+        Operator::Div | Operator::Mod if rhs == 0 => {
+            return Err(RuntimeError::new(
+                ErrorKind::DivisionByZero,
+                format!("`{} {} 0` divides by zero", lhs, op.symbol()),
+                span,
+            ))
+        }
+        Operator::Div | Operator::Mod if lhs == i32::MIN && rhs == -1 => {
+            return Err(RuntimeError::new(
+                ErrorKind::Overflow,
+                format!("`{} {} -1` overflows", lhs, op.symbol()),
+                span,
+            ))
+        }
+        Operator::Div => lhs / rhs,
+        Operator::Mod => lhs % rhs,
+        Operator::Gt => (lhs > rhs) as i32,
+        Operator::Ge => (lhs >= rhs) as i32,
+        Operator::Lt => (lhs < rhs) as i32,
+        Operator::Le => (lhs <= rhs) as i32,
+        Operator::Ne => (lhs != rhs) as i32,
+        Operator::Eq => (lhs == rhs) as i32,
+        Operator::As => unreachable!("the parser never produces Operator::As"),
+    };
+    Ok(value)
+}
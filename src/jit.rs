@@ -0,0 +1,215 @@
+extern crate llvm_sys as llvm;
+
+use crate::codegen::CodeGen;
+use crate::emit::take_message;
+use crate::runtime::read_int;
+use llvm::core::{
+    LLVMCountParams, LLVMGetElementType, LLVMGetNamedFunction, LLVMGetReturnType, LLVMGetTypeKind,
+    LLVMTypeOf,
+};
+use llvm::execution_engine::*;
+use llvm::prelude::LLVMModuleRef;
+use llvm::support::LLVMAddSymbol;
+use llvm::target::{LLVM_InitializeNativeAsmPrinter, LLVM_InitializeNativeTarget};
+use llvm::LLVMTypeKind;
+use std::cell::RefCell;
+use std::ffi::CStr;
+use std::io::{self, BufRead, Write};
+use std::mem;
+use std::os::raw::{c_char, c_void};
+use std::panic::{self, AssertUnwindSafe};
+use std::process;
+use std::ptr;
+
+/// Where `input()` reads from and `output()` writes to while a program runs
+/// in the JIT. `output` is `None` to write straight to standard output.
+struct Io {
+    input: Box<dyn BufRead>,
+    output: Option<Vec<u8>>,
+    error: Option<String>,
+}
+
+thread_local! {
+    // The JIT calls `main` on the calling thread, so the built-ins find the
+    // I/O of the program that is running there.
+    static IO: RefCell<Option<Io>> = const { RefCell::new(None) };
+}
+
+/// Unwinds out of the running program when a bounds check fails, the way
+/// the linked runtime exits the process.
+struct BoundsTrap(String);
+
+/// Runs the program's `main` in-process and returns its result, with
+/// `input()` and `output()` bound to the process's standard input and output.
+/// A failed bounds check is reported on standard error and the result is 1,
+/// as with the linked runtime.
+pub fn run(codegen: &CodeGen) -> Result<i32, String> {
+    let io = Io {
+        input: Box::new(io::stdin().lock()),
+        output: None,
+        error: None,
+    };
+    let (result, io) = execute(codegen, io)?;
+    match io.error {
+        Some(error) => {
+            eprintln!("{}", error);
+            Ok(1)
+        }
+        None => Ok(result),
+    }
+}
+
+/// Runs the program's `main` in-process, feeding it `input` and collecting
+/// what it prints. Malformed input is reported as an error instead of ending
+/// the process the way the linked runtime does, and so is a failed bounds
+/// check.
+#[cfg(test)]
+pub fn run_with_input(codegen: &CodeGen, input: &str) -> Result<(i32, String), String> {
+    let io = Io {
+        input: Box::new(io::Cursor::new(input.as_bytes().to_vec())),
+        output: Some(Vec::new()),
+        error: None,
+    };
+    let (result, io) = execute(codegen, io)?;
+    if let Some(error) = io.error {
+        return Err(error);
+    }
+    Ok((
+        result,
+        String::from_utf8_lossy(&io.output.unwrap_or_default()).into_owned(),
+    ))
+}
+
+fn execute(codegen: &CodeGen, io: Io) -> Result<(i32, Io), String> {
+    let module = codegen.module();
+    unsafe {
+        let main = LLVMGetNamedFunction(module, c"main".as_ptr());
+        if main.is_null() {
+            return Err("the program has no `main` function".to_string());
+        }
+        if LLVMCountParams(main) != 0 {
+            return Err("`main` must not take parameters to be run".to_string());
+        }
+        let returns_int = LLVMGetTypeKind(LLVMGetReturnType(LLVMGetElementType(LLVMTypeOf(main))))
+            == LLVMTypeKind::LLVMIntegerTypeKind;
+
+        LLVMLinkInMCJIT();
+        if LLVM_InitializeNativeTarget() != 0 || LLVM_InitializeNativeAsmPrinter() != 0 {
+            return Err("the native target is not available in this LLVM build".to_string());
+        }
+        LLVMAddSymbol(c"input".as_ptr(), jit_input as *mut c_void);
+        LLVMAddSymbol(c"output".as_ptr(), jit_output as *mut c_void);
+        LLVMAddSymbol(
+            c"__bounds_check_failed".as_ptr(),
+            jit_bounds_check_failed as *mut c_void,
+        );
+
+        let mut options: LLVMMCJITCompilerOptions = mem::zeroed();
+        LLVMInitializeMCJITCompilerOptions(
+            &mut options,
+            mem::size_of::<LLVMMCJITCompilerOptions>(),
+        );
+        let mut engine = ptr::null_mut();
+        let mut error = ptr::null_mut();
+        if LLVMCreateMCJITCompilerForModule(
+            &mut engine,
+            module,
+            &mut options,
+            mem::size_of::<LLVMMCJITCompilerOptions>(),
+            &mut error,
+        ) != 0
+        {
+            return Err(take_message(error));
+        }
+
+        let address = LLVMGetFunctionAddress(engine, c"main".as_ptr());
+        if address == 0 {
+            dispose_engine(engine, module);
+            return Err("the JIT could not compile `main`".to_string());
+        }
+        IO.with(|slot| *slot.borrow_mut() = Some(io));
+        let result = panic::catch_unwind(AssertUnwindSafe(|| {
+            if returns_int {
+                let main: extern "C-unwind" fn() -> i32 = mem::transmute(address as usize);
+                main()
+            } else {
+                let main: extern "C-unwind" fn() = mem::transmute(address as usize);
+                main();
+                0
+            }
+        }));
+        let mut io = IO.with(|slot| slot.borrow_mut().take()).unwrap();
+        let result = match result {
+            Ok(result) => result,
+            Err(payload) => match payload.downcast::<BoundsTrap>() {
+                Ok(trap) => {
+                    io.error = Some(trap.0);
+                    1
+                }
+                Err(payload) => {
+                    dispose_engine(engine, module);
+                    panic::resume_unwind(payload)
+                }
+            },
+        };
+        dispose_engine(engine, module);
+        Ok((result, io))
+    }
+}
+
+/// Takes the module back from the engine before disposing of it, since the
+/// module belongs to the `CodeGen` that disposes it.
+unsafe fn dispose_engine(engine: LLVMExecutionEngineRef, module: LLVMModuleRef) {
+    let mut removed = ptr::null_mut();
+    let mut error = ptr::null_mut();
+    LLVMRemoveModule(engine, module, &mut removed, &mut error);
+    LLVMDisposeExecutionEngine(engine);
+}
+
+extern "C" fn jit_input() -> i32 {
+    IO.with(|slot| {
+        let mut slot = slot.borrow_mut();
+        let io = slot.as_mut().expect("input() called outside the JIT");
+        match read_int(&mut io.input) {
+            Some(value) => value,
+            None if io.output.is_some() => {
+                io.error
+                    .get_or_insert_with(|| "input: expected an integer".to_string());
+                0
+            }
+            None => {
+                eprintln!("input: expected an integer");
+                process::exit(1);
+            }
+        }
+    })
+}
+
+extern "C" fn jit_output(x: i32) {
+    IO.with(|slot| {
+        let mut slot = slot.borrow_mut();
+        let io = slot.as_mut().expect("output() called outside the JIT");
+        match &mut io.output {
+            Some(buffer) => writeln!(buffer, "{}", x).unwrap(),
+            None => println!("{}", x),
+        }
+    })
+}
+
+/// Stands in for the runtime's `__bounds_check_failed`. The generated code
+/// does not continue after the call, so it unwinds back to `execute`.
+extern "C-unwind" fn jit_bounds_check_failed(
+    location: *const c_char,
+    array: *const c_char,
+    index: i32,
+    length: i32,
+) {
+    let text = |string| unsafe { CStr::from_ptr(string).to_string_lossy() };
+    panic::resume_unwind(Box::new(BoundsTrap(format!(
+        "{}: index {} out of bounds for array `{}` of length {}",
+        text(location),
+        index,
+        text(array),
+        length
+    ))));
+}
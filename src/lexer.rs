@@ -0,0 +1,279 @@
+use crate::diagnostic::{Code, Diagnostic};
+use crate::span::Span;
+use std::fmt::{self, Display, Formatter};
+
+#[derive(PartialEq, Eq, Debug, Copy, Clone)]
+pub enum Token<'input> {
+    Identifier(&'input str),
+    Number(i32),
+    Else,
+    If,
+    Int,
+    Return,
+    Void,
+    While,
+    Plus,
+    Minus,
+    Star,
+    Slash,
+    Percent,
+    Less,
+    LessEqual,
+    Greater,
+    GreaterEqual,
+    EqualEqual,
+    NotEqual,
+    Equal,
+    Semicolon,
+    Comma,
+    LeftParen,
+    RightParen,
+    LeftBracket,
+    RightBracket,
+    LeftBrace,
+    RightBrace,
+}
+
+impl Token<'_> {
+    /// What `--emit=tokens` calls the token: keyword, identifier, number or
+    /// punctuation.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Token::Identifier(_) => "identifier",
+            Token::Number(_) => "number",
+            Token::Else | Token::If | Token::Int | Token::Return | Token::Void | Token::While => {
+                "keyword"
+            }
+            _ => "punctuation",
+        }
+    }
+}
+
+impl Display for Token<'_> {
+    fn fmt(&self, fmt: &mut Formatter) -> fmt::Result {
+        let text = match self {
+            Token::Identifier(name) => return write!(fmt, "{}", name),
+            Token::Number(value) => return write!(fmt, "{}", value),
+            Token::Else => "else",
+            Token::If => "if",
+            Token::Int => "int",
+            Token::Return => "return",
+            Token::Void => "void",
+            Token::While => "while",
+            Token::Plus => "+",
+            Token::Minus => "-",
+            Token::Star => "*",
+            Token::Slash => "/",
+            Token::Percent => "%",
+            Token::Less => "<",
+            Token::LessEqual => "<=",
+            Token::Greater => ">",
+            Token::GreaterEqual => ">=",
+            Token::EqualEqual => "==",
+            Token::NotEqual => "!=",
+            Token::Equal => "=",
+            Token::Semicolon => ";",
+            Token::Comma => ",",
+            Token::LeftParen => "(",
+            Token::RightParen => ")",
+            Token::LeftBracket => "[",
+            Token::RightBracket => "]",
+            Token::LeftBrace => "{",
+            Token::RightBrace => "}",
+        };
+        write!(fmt, "{}", text)
+    }
+}
+
+/// A token with the byte offsets of its start and end, the form the parser
+/// takes its input in.
+pub type Spanned<'input> = (usize, Token<'input>, usize);
+
+/// A `// line` or `/* block */` comment, with its delimiters. The grammar
+/// never sees comments; they are kept for the formatter.
+#[derive(PartialEq, Debug, Clone)]
+pub struct Comment<'input> {
+    pub text: &'input str,
+    pub span: Span,
+}
+
+#[derive(PartialEq, Debug, Default)]
+pub struct Lexed<'input> {
+    pub tokens: Vec<Spanned<'input>>,
+    pub comments: Vec<Comment<'input>>,
+}
+
+/// Splits the source into tokens and comments. Whitespace may separate any
+/// two tokens and is otherwise ignored. Every error in the file is reported,
+/// not only the first.
+pub fn lex(source: &str) -> Result<Lexed<'_>, Vec<Diagnostic>> {
+    let mut lexer = Lexer {
+        source,
+        position: 0,
+        lexed: Lexed::default(),
+        diagnostics: Vec::new(),
+    };
+    while let Some(byte) = lexer.peek(0) {
+        match byte {
+            _ if byte.is_ascii_whitespace() => lexer.position += 1,
+            b'/' if lexer.peek(1) == Some(b'/') => lexer.line_comment(),
+            b'/' if lexer.peek(1) == Some(b'*') => lexer.block_comment(),
+            b'0'..=b'9' => lexer.number(),
+            b'a'..=b'z' | b'A'..=b'Z' | b'_' => lexer.word(),
+            _ => lexer.symbol(),
+        }
+    }
+    if lexer.diagnostics.is_empty() {
+        Ok(lexer.lexed)
+    } else {
+        Err(lexer.diagnostics)
+    }
+}
+
+struct Lexer<'input> {
+    source: &'input str,
+    position: usize,
+    lexed: Lexed<'input>,
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl<'input> Lexer<'input> {
+    fn peek(&self, offset: usize) -> Option<u8> {
+        self.source.as_bytes().get(self.position + offset).copied()
+    }
+
+    /// Advances past the bytes that satisfy `accept` and returns them.
+    fn take_while(&mut self, accept: impl Fn(u8) -> bool) -> &'input str {
+        let start = self.position;
+        while self.peek(0).is_some_and(&accept) {
+            self.position += 1;
+        }
+        &self.source[start..self.position]
+    }
+
+    fn push(&mut self, start: usize, token: Token<'input>) {
+        self.lexed.tokens.push((start, token, self.position));
+    }
+
+    fn line_comment(&mut self) {
+        let start = self.position;
+        self.take_while(|byte| byte != b'\n' && byte != b'\r');
+        self.push_comment(start);
+    }
+
+    /// A block comment ends at the first `*/`, so a `/*` inside one is an
+    /// error rather than a nested comment.
+    fn block_comment(&mut self) {
+        let start = self.position;
+        self.position += 2;
+        loop {
+            match (self.peek(0), self.peek(1)) {
+                (Some(b'*'), Some(b'/')) => {
+                    self.position += 2;
+                    break;
+                }
+                (Some(b'/'), Some(b'*')) => {
+                    self.diagnostics.push(
+                        Diagnostic::error(
+                            Code::NestedComment,
+                            "`/*` inside a block comment",
+                            Span::new(self.position, self.position + 2),
+                        )
+                        .with_label(Span::new(start, start + 2), "the comment starts here")
+                        .with_note("block comments do not nest; the first `*/` ends them"),
+                    );
+                    self.position += 2;
+                }
+                (Some(_), _) => self.position += 1,
+                (None, _) => {
+                    self.diagnostics.push(
+                        Diagnostic::error(
+                            Code::UnterminatedComment,
+                            "unterminated block comment",
+                            Span::new(start, start + 2),
+                        )
+                        .with_note("a block comment ends with `*/`"),
+                    );
+                    break;
+                }
+            }
+        }
+        self.push_comment(start);
+    }
+
+    fn push_comment(&mut self, start: usize) {
+        self.lexed.comments.push(Comment {
+            text: &self.source[start..self.position],
+            span: Span::new(start, self.position),
+        });
+    }
+
+    fn number(&mut self) {
+        let start = self.position;
+        let digits = self.take_while(|byte| byte.is_ascii_digit());
+        match digits.parse() {
+            Ok(value) => self.push(start, Token::Number(value)),
+            Err(_) => self.diagnostics.push(
+                Diagnostic::error(
+                    Code::LiteralOutOfRange,
+                    &format!("integer literal `{}` is out of range", digits),
+                    Span::new(start, self.position),
+                )
+                .with_note(&format!("the largest int is {}", i32::MAX)),
+            ),
+        }
+    }
+
+    fn word(&mut self) {
+        let start = self.position;
+        let word = self.take_while(|byte| byte.is_ascii_alphanumeric() || byte == b'_');
+        let token = match word {
+            "else" => Token::Else,
+            "if" => Token::If,
+            "int" => Token::Int,
+            "return" => Token::Return,
+            "void" => Token::Void,
+            "while" => Token::While,
+            _ => Token::Identifier(word),
+        };
+        self.push(start, token);
+    }
+
+    fn symbol(&mut self) {
+        let start = self.position;
+        let (token, length) = match (self.peek(0).unwrap(), self.peek(1)) {
+            (b'<', Some(b'=')) => (Token::LessEqual, 2),
+            (b'>', Some(b'=')) => (Token::GreaterEqual, 2),
+            (b'=', Some(b'=')) => (Token::EqualEqual, 2),
+            (b'!', Some(b'=')) => (Token::NotEqual, 2),
+            (b'+', _) => (Token::Plus, 1),
+            (b'-', _) => (Token::Minus, 1),
+            (b'*', _) => (Token::Star, 1),
+            (b'/', _) => (Token::Slash, 1),
+            (b'%', _) => (Token::Percent, 1),
+            (b'<', _) => (Token::Less, 1),
+            (b'>', _) => (Token::Greater, 1),
+            (b'=', _) => (Token::Equal, 1),
+            (b';', _) => (Token::Semicolon, 1),
+            (b',', _) => (Token::Comma, 1),
+            (b'(', _) => (Token::LeftParen, 1),
+            (b')', _) => (Token::RightParen, 1),
+            (b'[', _) => (Token::LeftBracket, 1),
+            (b']', _) => (Token::RightBracket, 1),
+            (b'{', _) => (Token::LeftBrace, 1),
+            (b'}', _) => (Token::RightBrace, 1),
+            _ => {
+                let found = self.source[start..].chars().next().unwrap();
+                self.position += found.len_utf8();
+                self.diagnostics.push(Diagnostic::error(
+                    Code::InvalidCharacter,
+                    &format!("unexpected character `{}`", found.escape_debug()),
+                    Span::new(start, self.position),
+                ));
+                return;
+            }
+        };
+        self.position += length;
+        self.push(start, token);
+    }
+}
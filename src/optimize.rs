@@ -0,0 +1,106 @@
+extern crate llvm_sys as llvm;
+
+use crate::codegen::CodeGen;
+use crate::emit::TargetMachine;
+use llvm::core::*;
+use llvm::target_machine::{LLVMAddAnalysisPasses, LLVMCodeGenOptLevel};
+use llvm::transforms::pass_manager_builder::*;
+
+#[derive(PartialEq, Debug, Copy, Clone, Default)]
+pub enum OptLevel {
+    #[default]
+    O0,
+    O1,
+    O2,
+    O3,
+    Os,
+}
+
+impl OptLevel {
+    pub fn from_flag(flag: &str) -> Option<OptLevel> {
+        match flag {
+            "-O0" => Some(OptLevel::O0),
+            "-O1" => Some(OptLevel::O1),
+            "-O2" => Some(OptLevel::O2),
+            "-O3" => Some(OptLevel::O3),
+            "-Os" => Some(OptLevel::Os),
+            _ => None,
+        }
+    }
+
+    /// LLVM's speed level (0 to 3) and size level (0 to 2).
+    fn levels(self) -> (u32, u32) {
+        match self {
+            OptLevel::O0 => (0, 0),
+            OptLevel::O1 => (1, 0),
+            OptLevel::O2 => (2, 0),
+            OptLevel::O3 => (3, 0),
+            OptLevel::Os => (2, 1),
+        }
+    }
+
+    /// The matching level of the code generator, which does instruction
+    /// selection and scheduling after the passes.
+    pub fn codegen_level(self) -> LLVMCodeGenOptLevel {
+        match self {
+            OptLevel::O0 => LLVMCodeGenOptLevel::LLVMCodeGenLevelNone,
+            OptLevel::O1 => LLVMCodeGenOptLevel::LLVMCodeGenLevelLess,
+            OptLevel::O2 | OptLevel::Os => LLVMCodeGenOptLevel::LLVMCodeGenLevelDefault,
+            OptLevel::O3 => LLVMCodeGenOptLevel::LLVMCodeGenLevelAggressive,
+        }
+    }
+
+    /// The inliner threshold clang uses for the level, or `None` below -O2,
+    /// where only `alwaysinline` functions would be inlined.
+    fn inline_threshold(self) -> Option<u32> {
+        match self {
+            OptLevel::O0 | OptLevel::O1 => None,
+            OptLevel::O2 => Some(225),
+            OptLevel::O3 => Some(275),
+            OptLevel::Os => Some(75),
+        }
+    }
+}
+
+/// Runs LLVM's standard pass pipeline for the machine's level over the
+/// module: per function (mem2reg, instcombine, simplifycfg, ...) and then over
+/// the whole module (inlining, GVN, ...). The machine's analyses give the
+/// passes its costs, and the module is given its triple and data layout.
+/// -O0 leaves the module untouched otherwise.
+pub fn optimize(codegen: &CodeGen, machine: &TargetMachine) {
+    machine.configure(codegen);
+    let level = machine.opt_level();
+    if level == OptLevel::O0 {
+        return;
+    }
+    let module = codegen.module();
+    let (speed, size) = level.levels();
+    unsafe {
+        let builder = LLVMPassManagerBuilderCreate();
+        LLVMPassManagerBuilderSetOptLevel(builder, speed);
+        LLVMPassManagerBuilderSetSizeLevel(builder, size);
+        if let Some(threshold) = level.inline_threshold() {
+            LLVMPassManagerBuilderUseInlinerWithThreshold(builder, threshold);
+        }
+
+        let function_passes = LLVMCreateFunctionPassManagerForModule(module);
+        LLVMAddAnalysisPasses(machine.as_raw(), function_passes);
+        LLVMPassManagerBuilderPopulateFunctionPassManager(builder, function_passes);
+        LLVMInitializeFunctionPassManager(function_passes);
+        let mut function = LLVMGetFirstFunction(module);
+        while !function.is_null() {
+            LLVMRunFunctionPassManager(function_passes, function);
+            function = LLVMGetNextFunction(function);
+        }
+        LLVMFinalizeFunctionPassManager(function_passes);
+        LLVMDisposePassManager(function_passes);
+
+        let module_passes = LLVMCreatePassManager();
+        LLVMAddAnalysisPasses(machine.as_raw(), module_passes);
+        LLVMPassManagerBuilderPopulateModulePassManager(builder, module_passes);
+        LLVMRunPassManager(module_passes, module);
+        LLVMDisposePassManager(module_passes);
+
+        LLVMPassManagerBuilderDispose(builder);
+    }
+}
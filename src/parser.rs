@@ -0,0 +1,112 @@
+use crate::ast::Program;
+use crate::diagnostic::{Code, Diagnostic};
+use crate::grammar;
+use crate::lexer::{lex, Comment, Lexed, Token};
+use crate::span::Span;
+use crate::typecheck::MAX_ERRORS;
+use lalrpop_util::ParseError;
+
+/// Parses a whole program, recovering from syntax errors at statement and
+/// declaration boundaries so that every error in the file is reported, up to
+/// the same limit as typecheck's.
+pub fn parse_program(source: &str) -> Result<Box<Program>, Vec<Diagnostic>> {
+    parse_with_comments(source).map(|(program, _)| program)
+}
+
+/// Like `parse_program`, but also returns the comments in source order.
+/// Lexical errors are reported on their own, since the parser never sees the
+/// input they were found in.
+pub fn parse_with_comments(
+    source: &str,
+) -> Result<(Box<Program>, Vec<Comment<'_>>), Vec<Diagnostic>> {
+    let Lexed { tokens, comments } = lex(source)?;
+    let mut recovered = Vec::new();
+    let result = grammar::ProgramParser::new().parse(&mut recovered, tokens.into_iter().map(Ok));
+    let mut errors: Vec<_> = recovered
+        .into_iter()
+        .map(|recovery| recovery.error)
+        .collect();
+    match result {
+        Ok(program) if errors.is_empty() => return Ok((program, comments)),
+        Ok(_) => {}
+        Err(error) => errors.push(error),
+    }
+    let total = errors.len();
+    let mut diagnostics: Vec<Diagnostic> = errors
+        .into_iter()
+        .take(MAX_ERRORS)
+        .map(|error| parse_error_diagnostic(error, source))
+        .collect();
+    if total > MAX_ERRORS {
+        let note = format!(
+            "stopped reporting after {} errors; {} more not shown",
+            MAX_ERRORS,
+            total - MAX_ERRORS
+        );
+        diagnostics.last_mut().unwrap().notes.push(note);
+    }
+    Err(diagnostics)
+}
+
+fn parse_error_diagnostic(error: ParseError<usize, Token, Diagnostic>, source: &str) -> Diagnostic {
+    match error {
+        // The lexer reports these itself, so the parser never finds one.
+        ParseError::InvalidToken { location } => Diagnostic::error(
+            Code::SyntaxError,
+            "invalid token",
+            Span::new(location, location),
+        ),
+        ParseError::UnrecognizedEof { location, expected } => Diagnostic::error(
+            Code::SyntaxError,
+            &format!("{} but found end of file", describe_expected(&expected)),
+            Span::new(location, source.len()),
+        ),
+        ParseError::UnrecognizedToken {
+            token: (start, token, end),
+            expected,
+        } => Diagnostic::error(
+            Code::SyntaxError,
+            &format!("{} but found `{}`", describe_expected(&expected), token),
+            Span::new(start, end),
+        ),
+        ParseError::ExtraToken {
+            token: (start, token, end),
+        } => Diagnostic::error(
+            Code::SyntaxError,
+            &format!("unexpected `{}` after the end of the program", token),
+            Span::new(start, end),
+        ),
+        ParseError::User { error } => error,
+    }
+}
+
+/// The binary operators, which are listed as "an operator" when every one
+/// of them is expected.
+const OPERATORS: [&str; 11] = [
+    "`!=`", "`%`", "`*`", "`+`", "`-`", "`/`", "`<`", "`<=`", "`==`", "`>`", "`>=`",
+];
+
+/// Turns LALRPOP's expected terminals (the quoted names from the grammar's
+/// `extern` block, such as `"\";\""`) into "expected `(`, `;` or `[`".
+fn describe_expected(expected: &[String]) -> String {
+    let mut names: Vec<String> = expected
+        .iter()
+        .map(|terminal| match terminal.trim_matches('"') {
+            name @ ("identifier" | "number") => name.to_string(),
+            symbol => format!("`{}`", symbol),
+        })
+        .collect();
+    names.dedup();
+    if OPERATORS
+        .iter()
+        .all(|operator| names.iter().any(|name| name == operator))
+    {
+        names.retain(|name| !OPERATORS.contains(&name.as_str()));
+        names.push("an operator".to_string());
+    }
+    match names.split_last() {
+        None => "unexpected input".to_string(),
+        Some((last, [])) => format!("expected {}", last),
+        Some((last, rest)) => format!("expected {} or {}", rest.join(", "), last),
+    }
+}
@@ -0,0 +1,317 @@
+use crate::ast::{
+    CompoundStatement, Expression, FunctionDeclaration, IdentifierType, IfStatement, Operator,
+    Param, Program, Statement, Var, VarDeclaration,
+};
+use crate::lexer::Comment;
+
+const INDENT: &str = "    ";
+
+/// Prints a program back to source that parses to the same tree, in the
+/// canonical style: one declaration or statement per line, four-space
+/// indentation, a blank line before each function, and only the
+/// parentheses that the precedence of the operators requires.
+#[cfg(test)]
+pub fn print_program(program: &Program) -> String {
+    format_program(program, "", &[])
+}
+
+/// Prints a program like `print_program`, keeping the comments of the
+/// `source` it was parsed from. A comment on the same line as a declaration,
+/// a simple statement or a closing brace stays at the end of that line;
+/// any other comment goes on its own line before whatever follows it.
+pub fn format_program(program: &Program, source: &str, comments: &[Comment]) -> String {
+    let mut printer = Printer {
+        output: String::new(),
+        depth: 0,
+        source,
+        comments,
+    };
+    for declaration in &program.var_declarations {
+        printer.var_declaration(declaration);
+    }
+    for function in &program.fun_declarations {
+        if !printer.output.is_empty() {
+            printer.output.push('\n');
+        }
+        printer.function(function);
+    }
+    printer.comments_before(usize::MAX);
+    printer.output
+}
+
+pub fn print_expression(expression: &Expression) -> String {
+    match expression {
+        Expression::IntegerLiteral(value, _) => value.to_string(),
+        Expression::Var(var) => print_var(var),
+        Expression::Call(call) => {
+            let args: Vec<String> = call.args.iter().map(|arg| print_expression(arg)).collect();
+            format!("{}({})", call.name, args.join(", "))
+        }
+        Expression::Operation(lhs, op, rhs, _) => {
+            // Every tier is left-associative, so an operand on the right
+            // needs parentheses at the same precedence as well.
+            let tier = operator_tier(op);
+            format!(
+                "{} {} {}",
+                operand(lhs, tier),
+                op.symbol(),
+                operand(rhs, tier + 1)
+            )
+        }
+        Expression::Assignment(var, value, _) => {
+            format!("{} = {}", print_var(var), print_expression(value))
+        }
+    }
+}
+
+/// Prints an operand, in parentheses if it binds more loosely than `tier`.
+fn operand(expression: &Expression, tier: u8) -> String {
+    let binds = match expression {
+        Expression::Assignment(_, _, _) => 0,
+        Expression::Operation(_, op, _, _) => operator_tier(op),
+        _ => u8::MAX,
+    };
+    if binds < tier {
+        format!("({})", print_expression(expression))
+    } else {
+        print_expression(expression)
+    }
+}
+
+/// The precedence of an operator, following the `Tier` productions of the
+/// grammar: comparisons, then additive, then multiplicative operators.
+fn operator_tier(op: &Operator) -> u8 {
+    match op {
+        Operator::Gt | Operator::Ge | Operator::Lt | Operator::Le | Operator::Ne | Operator::Eq => {
+            1
+        }
+        Operator::Add | Operator::Sub => 2,
+        Operator::Mul | Operator::Div | Operator::Mod => 3,
+        Operator::As => 0,
+    }
+}
+
+fn print_var(var: &Var) -> String {
+    match var {
+        Var::Var(name, _) => name.clone(),
+        Var::ArrayAccess(name, index, _) => format!("{}[{}]", name, print_expression(index)),
+    }
+}
+
+fn type_name(identifier_type: IdentifierType) -> &'static str {
+    match identifier_type {
+        IdentifierType::Int => "int",
+        IdentifierType::Void => "void",
+    }
+}
+
+struct Printer<'a> {
+    output: String,
+    depth: usize,
+    source: &'a str,
+    /// The comments not printed yet.
+    comments: &'a [Comment<'a>],
+}
+
+impl Printer<'_> {
+    fn line(&mut self, text: &str) {
+        for _ in 0..self.depth {
+            self.output.push_str(INDENT);
+        }
+        self.output.push_str(text);
+        self.output.push('\n');
+    }
+
+    /// Prints the comments that start before `position`, each on its own line.
+    fn comments_before(&mut self, position: usize) {
+        while let Some((comment, rest)) = self.comments.split_first() {
+            if comment.span.start >= position {
+                break;
+            }
+            self.comments = rest;
+            self.line(comment.text);
+        }
+    }
+
+    /// Appends to the last line the comments that start before `end`, where
+    /// the source text that line came from ends, or after it with nothing
+    /// but a `;` in between.
+    fn comments_after(&mut self, mut end: usize) {
+        while let Some((comment, rest)) = self.comments.split_first() {
+            if comment.span.start >= end
+                && !self.source[end..comment.span.start]
+                    .chars()
+                    .all(|c| c == ';' || c == ' ' || c == '\t')
+            {
+                break;
+            }
+            self.comments = rest;
+            end = end.max(comment.span.end);
+            self.output.pop();
+            self.output.push(' ');
+            self.output.push_str(comment.text);
+            self.output.push('\n');
+        }
+    }
+
+    fn var_declaration(&mut self, declaration: &VarDeclaration) {
+        self.comments_before(declaration.span().start);
+        let text = match declaration {
+            VarDeclaration::VarDeclaration(identifier_type, name, _) => {
+                format!("{} {};", type_name(*identifier_type), name)
+            }
+            VarDeclaration::ArrDeclaration(identifier_type, name, size, _) => {
+                format!("{} {}[{}];", type_name(*identifier_type), name, size)
+            }
+        };
+        self.line(&text);
+        self.comments_after(declaration.span().end);
+    }
+
+    fn function(&mut self, function: &FunctionDeclaration) {
+        let params: Vec<String> = function
+            .params
+            .iter()
+            .map(|param| match param {
+                Param::Var(identifier_type, name, _) => {
+                    format!("{} {}", type_name(*identifier_type), name)
+                }
+                Param::ArrVar(identifier_type, name, _) => {
+                    format!("{} {}[]", type_name(*identifier_type), name)
+                }
+            })
+            .collect();
+        self.comments_before(function.span.start);
+        self.line(&format!(
+            "{} {}({}) {{",
+            type_name(function.return_type),
+            function.function_name,
+            params.join(", ")
+        ));
+        self.block_contents(&function.body);
+        self.line("}");
+        self.comments_after(function.span.end);
+    }
+
+    fn block_contents(&mut self, block: &CompoundStatement) {
+        self.depth += 1;
+        for declaration in &block.declarations {
+            self.var_declaration(declaration);
+        }
+        for statement in &block.statements {
+            self.statement(statement);
+        }
+        self.comments_before(block.span.end);
+        self.depth -= 1;
+    }
+
+    fn statement(&mut self, statement: &Statement) {
+        self.comments_before(statement.span().start);
+        self.statement_with_prefix("", statement);
+    }
+
+    /// Prints `statement` with `prefix` (such as `} else `) in front of its
+    /// first line. Comments before an `else` wait for the statement's body,
+    /// since a line between `}` and `else` would belong to the block.
+    fn statement_with_prefix(&mut self, prefix: &str, statement: &Statement) {
+        match statement {
+            Statement::Expression(expression) => {
+                self.line(&format!("{}{};", prefix, print_expression(expression)));
+                self.comments_after(expression.span().end);
+            }
+            Statement::ReturnStatement(Some(expression), span) => {
+                self.line(&format!(
+                    "{}return {};",
+                    prefix,
+                    print_expression(expression)
+                ));
+                self.comments_after(span.end);
+            }
+            Statement::ReturnStatement(None, span) => {
+                self.line(&format!("{}return;", prefix));
+                self.comments_after(span.end);
+            }
+            Statement::EmptyStatement(span) => {
+                self.line(&format!("{};", prefix));
+                self.comments_after(span.end);
+            }
+            Statement::CompoundStatement(block) => {
+                self.line(&format!("{}{{", prefix));
+                self.block_contents(block);
+                self.line("}");
+                self.comments_after(block.span.end);
+            }
+            Statement::WhileStatement(while_statement) => {
+                let header = format!(
+                    "{}while ({})",
+                    prefix,
+                    print_expression(&while_statement.condition)
+                );
+                self.body(&header, &while_statement.statement, false);
+            }
+            Statement::IfStatement(if_statement) => match &**if_statement {
+                IfStatement::IfStmt(condition, then_statement, _) => {
+                    let header = format!("{}if ({})", prefix, print_expression(condition));
+                    self.body(&header, then_statement, false);
+                }
+                IfStatement::IfElseStmt(condition, then_statement, else_statement, _) => {
+                    let header = format!("{}if ({})", prefix, print_expression(condition));
+                    let braced = self.body(&header, then_statement, true);
+                    let else_prefix = if braced { "} else" } else { "else" };
+                    if let Statement::IfStatement(_) = &**else_statement {
+                        self.statement_with_prefix(&format!("{} ", else_prefix), else_statement);
+                    } else {
+                        self.body(else_prefix, else_statement, false);
+                    }
+                }
+            },
+        }
+    }
+
+    /// Prints `header` followed by the body of an `if`, `else` or `while`:
+    /// on the same line when it is a block, indented on the next otherwise.
+    /// Before an `else` a block is left open for `} else`. Returns whether the
+    /// body was a block.
+    fn body(&mut self, header: &str, statement: &Statement, before_else: bool) -> bool {
+        let braced = match statement {
+            Statement::CompoundStatement(block) => {
+                self.line(&format!("{} {{", header));
+                self.block_contents(block);
+                true
+            }
+            // An `if` without an `else` would take the `else` that follows,
+            // so it goes in braces, which only adds a block around it.
+            _ if before_else && ends_in_open_if(statement) => {
+                self.line(&format!("{} {{", header));
+                self.depth += 1;
+                self.statement(statement);
+                self.depth -= 1;
+                true
+            }
+            _ => {
+                self.line(header);
+                self.depth += 1;
+                self.statement(statement);
+                self.depth -= 1;
+                false
+            }
+        };
+        if braced && !before_else {
+            self.line("}");
+            self.comments_after(statement.span().end);
+        }
+        braced
+    }
+}
+
+/// Whether an `else` after `statement` would attach to an `if` inside it.
+fn ends_in_open_if(statement: &Statement) -> bool {
+    match statement {
+        Statement::IfStatement(if_statement) => match &**if_statement {
+            IfStatement::IfStmt(_, _, _) => true,
+            IfStatement::IfElseStmt(_, _, else_statement, _) => ends_in_open_if(else_statement),
+        },
+        Statement::WhileStatement(while_statement) => ends_in_open_if(&while_statement.statement),
+        _ => false,
+    }
+}
@@ -0,0 +1,56 @@
+use crate::ast::IdentifierType;
+use crate::symbol_table::Param;
+use std::io::BufRead;
+
+/// A function every program can call without declaring it. Calls are
+/// type-checked against `return_type` and `params`, and resolved at link time
+/// against the C runtime in `OBJECT`.
+pub struct Builtin {
+    pub name: &'static str,
+    pub return_type: IdentifierType,
+    pub params: &'static [Param],
+}
+
+/// `int input(void)` and `void output(int x)`, as in C-minus.
+pub const BUILTINS: &[Builtin] = &[
+    Builtin {
+        name: "input",
+        return_type: IdentifierType::Int,
+        params: &[],
+    },
+    Builtin {
+        name: "output",
+        return_type: IdentifierType::Void,
+        params: &[Param::Var],
+    },
+];
+
+/// The runtime in runtime/runtime.c, compiled by the build script and linked
+/// into every executable.
+pub const OBJECT: &[u8] = include_bytes!(concat!(env!("OUT_DIR"), "/runtime.o"));
+
+pub fn builtin(name: &str) -> Option<&'static Builtin> {
+    BUILTINS.iter().find(|builtin| builtin.name == name)
+}
+
+/// Reads an integer the way `scanf("%d")` does: leading whitespace, an
+/// optional sign, then digits. The JIT and the interpreter use it to stand
+/// in for the C runtime's `input()`.
+pub fn read_int(input: &mut dyn BufRead) -> Option<i32> {
+    let mut text = String::new();
+    while let Some(&byte) = input.fill_buf().ok()?.first() {
+        let accept = if byte.is_ascii_whitespace() {
+            text.is_empty()
+        } else {
+            byte.is_ascii_digit() || (text.is_empty() && (byte == b'-' || byte == b'+'))
+        };
+        if !accept {
+            break;
+        }
+        if !byte.is_ascii_whitespace() {
+            text.push(byte as char);
+        }
+        input.consume(1);
+    }
+    text.parse::<i64>().ok().map(|value| value as i32)
+}
@@ -0,0 +1,85 @@
+use std::fmt::{Debug, Formatter};
+
+/// A half-open range of byte offsets `[start, end)` into the source text.
+#[derive(Copy, Clone, Default, PartialEq)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+}
+
+impl Span {
+    pub fn new(start: usize, end: usize) -> Span {
+        Span { start, end }
+    }
+}
+
+impl Debug for Span {
+    fn fmt(&self, fmt: &mut Formatter) -> std::fmt::Result {
+        write!(fmt, "{}..{}", self.start, self.end)
+    }
+}
+
+pub struct SourceFile {
+    pub name: String,
+    pub contents: String,
+    line_starts: Vec<usize>,
+}
+
+impl SourceFile {
+    pub fn new(name: &str, contents: &str) -> SourceFile {
+        let mut line_starts = vec![0];
+        for (i, c) in contents.char_indices() {
+            if c == '\n' {
+                line_starts.push(i + 1);
+            }
+        }
+        SourceFile {
+            name: name.to_string(),
+            contents: contents.to_string(),
+            line_starts,
+        }
+    }
+
+    /// Returns the 1-based line and column of a byte offset.
+    pub fn line_col(&self, offset: usize) -> (usize, usize) {
+        let offset = offset.min(self.contents.len());
+        let line = match self.line_starts.binary_search(&offset) {
+            Ok(line) => line,
+            Err(line) => line - 1,
+        };
+        let column = self.contents[self.line_starts[line]..offset].chars().count() + 1;
+        (line + 1, column)
+    }
+
+    /// Returns the text of a 1-based line without its line terminator.
+    pub fn line_text(&self, line: usize) -> &str {
+        let start = self.line_starts[line - 1];
+        let end = match self.line_starts.get(line) {
+            Some(next) => *next,
+            None => self.contents.len(),
+        };
+        self.contents[start..end].trim_end_matches(['\n', '\r'])
+    }
+
+    pub fn location(&self, span: Span) -> String {
+        let (line, column) = self.line_col(span.start);
+        format!("{}:{}:{}", self.name, line, column)
+    }
+
+    /// Returns the source line the span starts on, followed by a line that
+    /// underlines the span with `marker`.
+    pub fn snippet(&self, span: Span, marker: char) -> String {
+        let (line, column) = self.line_col(span.start);
+        let text = self.line_text(line);
+        let mut underline = String::new();
+        for c in text.chars().take(column - 1) {
+            underline.push(if c == '\t' { '\t' } else { ' ' });
+        }
+        let start = span.start.min(self.contents.len());
+        let end = span.end.clamp(start, self.contents.len());
+        let remaining = text.chars().count() + 1 - column;
+        let width = self.contents[start..end].chars().count();
+        underline.push_str(&marker.to_string().repeat(width.clamp(1, remaining.max(1))));
+        format!("{}\n{}", text, underline)
+    }
+}
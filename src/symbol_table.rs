@@ -1,11 +1,12 @@
+
 use std::collections::HashMap;
 use std::sync::Arc;
-use crate::ast::{IdentifierType};
+use crate::ast::IdentifierType;
+use crate::span::Span;
 
 pub struct SymbolTable {
     pub current_scope:  HashMap<String, SymbolTableElement>,
     pub parent_scope: Option<Arc<SymbolTable>>,
-    pub depth: i32
 }
 
 pub enum SymbolTableElement {
@@ -16,30 +17,27 @@ pub enum SymbolTableElement {
 }
 
 pub struct VariableSymbolTableElement {
-    pub element_type: IdentifierType,
-    pub depth: i32
+    pub span: Span
 }
 
 pub struct ArraySymbolTableElement {
-    pub element_type: IdentifierType,
-    pub size: i32,
-    pub depth: i32
+    pub span: Span
 }
 
 pub struct ParameterArraySymbolTableElement {
-    pub element_type: IdentifierType,
-    pub depth: i32
+    pub span: Span
 }
 
 pub struct FunctionSymbolTableElement {
     pub return_type: IdentifierType,
     pub argument_types: Vec<Param>,
-    pub depth: i32,
+    pub span: Span,
 }
 
+#[derive(Copy, Clone)]
 pub enum Param {
-    Var(IdentifierType),
-    Arr(IdentifierType)
+    Var,
+    Arr
 }
 
 #[derive(PartialEq)]
@@ -47,6 +45,28 @@ pub enum ExpressionType {
     Array,
     Int,
     Void,
+    // The type of an expression that has already been reported as an error.
+    Error,
+}
+
+impl ExpressionType {
+    /// Whether a value of this type can be used where `expected` is required.
+    /// `Error` conforms both ways so a single mistake is only reported once.
+    pub fn conforms_to(&self, expected: &ExpressionType) -> bool {
+        self == expected || *self == ExpressionType::Error || *expected == ExpressionType::Error
+    }
+}
+
+impl SymbolTableElement {
+    /// The span of the declaration that introduced the symbol.
+    pub fn span(&self) -> Span {
+        match self {
+            SymbolTableElement::Variable(element) => element.span,
+            SymbolTableElement::Array(element) => element.span,
+            SymbolTableElement::ParameterArray(element) => element.span,
+            SymbolTableElement::Function(element) => element.span,
+        }
+    }
 }
 
 impl SymbolTable {
@@ -62,19 +82,17 @@ impl SymbolTable {
 
     pub fn symbol_insert(&mut self, id: &str, element: SymbolTableElement) -> bool {
         if self.current_scope.contains_key(id) {
-            return false;
+            false
         } else {
             self.current_scope.insert(id.to_string(), element);
-            return true;
+            true
         }
     }
 }
 
 pub fn get_child_table(parent: Arc<SymbolTable>) -> SymbolTable {
-    let depth = *&parent.depth;
     SymbolTable {
         parent_scope: Some(parent),
         current_scope: HashMap::new(),
-        depth
     }
 }
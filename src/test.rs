@@ -4,15 +4,48 @@ mod tests {
         CompoundStatement, Expression, FunctionCall, FunctionDeclaration, IdentifierType,
         IfStatement, Operator, Param, Statement, Var, VarDeclaration, WhileStatement,
     };
-    use crate::typecheck::{typecheck_program, TypecheckProgramResult};
-    use crate::codegen::{codegen};
-
-    lalrpop_mod!(pub grammar); // synthesized by LALRPOP
+    use crate::diagnostic::{Code, Diagnostic};
+    use crate::grammar;
+    use crate::parser::parse_program;
+    use crate::span::{SourceFile, Span};
+    use crate::typecheck::{typecheck_program, TypecheckProgramResult, MAX_ERRORS};
+    use crate::codegen::{codegen, CodegenOptions};
+    use crate::emit::{link, OutputKind, TargetMachine};
+    use crate::cli::{parse_args, Emit};
+    use crate::optimize::{optimize, OptLevel};
+    use crate::lexer::{lex, Token};
+    use crate::jit::run_with_input;
+    use crate::interp::{interpret, ErrorKind, Limits, RuntimeError};
+    use crate::difftest::{compare, fuzz, run_corpus, without_spans, Verdict};
+    use crate::generate::generate;
+    use crate::printer::{format_program, print_program};
+    use crate::parser::parse_with_comments;
+    use std::io::Write;
 
     static EXPRESSION_TEST: &str = "0*(1+2)+(3*4-e(1))";
 
     static ASSOCIATIVITY_TEST: &str = "0+1-2";
 
+    static MODULO_TEST: &str = "1+7%2";
+
+    static SIGNED_ARITHMETIC_TEST: &str = "
+        int a[2];
+        int main() {
+            int q;
+            int r;
+            q = 0 - 7;
+            r = q % 2;
+            q = q / 2;
+            while (q < r) {
+                q = q + (q != r);
+            }
+            return q == r;
+        }
+        int bad() {
+            return a % 2;
+        }
+    ";
+
     static VAR_DECLARATIONS_TEST: &str = "
         int test;
         int test2[4];
@@ -52,7 +85,7 @@ mod tests {
 
     static DANGLING_ELSE: &str = "
         int a;
-        int func() {
+        void func() {
             int b;
             if (a)
                 if (b)
@@ -62,197 +95,1175 @@ mod tests {
         }
 ";
 
+    static UNDECLARED_CALL_TEST: &str = "int a;
+int func() {
+    a = 1;
+    a = missing(a);
+}
+";
+
+    static DUPLICATE_DECLARATION_TEST: &str = "
+        int a;
+        int a[3];
+    ";
+
+    static MUTUAL_RECURSION_TEST: &str = "
+        int even(int n) {
+            if (n == 0) return 1;
+            return odd(n - 1);
+        }
+        int odd(int n) {
+            if (n == 0) return 0;
+            return even(n - 1);
+        }
+        int main() {
+            return even(10);
+        }
+    ";
+
+    static BUILTINS_TEST: &str = "
+        int main() {
+            int n;
+            n = input();
+            while (n > 0) {
+                output(n * n);
+                n = n - 1;
+            }
+            return 0;
+        }
+    ";
+
+    static BUILTIN_MISUSE_TEST: &str = "
+        int input;
+        void output(int x) { }
+        int main() {
+            output(1, 2);
+            return 0;
+        }
+    ";
+
+    static RUNTIME_ERRORS_TEST: &str = "
+        int get(int a[], int i) {
+            return a[i] + 0;
+        }
+        int divide(int n, int d) {
+            return n / d;
+        }
+        int forever(int n) {
+            while (n >= 0) n = n + 1;
+            return n;
+        }
+        int deep(int n) {
+            return deep(n + 1);
+        }
+        int main() {
+            int a[2];
+            int which;
+            which = input();
+            if (which == 1) return get(a, 2);
+            if (which == 2) return divide(7, 0);
+            if (which == 3) return divide(0 - 2147483647 - 1, 0 - 1);
+            if (which == 4) return forever(0);
+            if (which == 5) return deep(0);
+            return (2147483647 + 1) / 65536;
+        }
+    ";
+
+    static GLOBALS_TEST: &str = "
+        int counter;
+        int values[4];
+        void bump() {
+            counter = counter + 1;
+            values[counter] = counter;
+        }
+        int main() {
+            bump();
+            bump();
+            return values[1] + counter;
+        }
+    ";
+
+    static ARRAY_PARAMETER_TEST: &str = "
+        int g[3];
+        int first(int a[]) {
+            return a[0] + 0;
+        }
+        int fill(int a[], int n) {
+            a[n] = n;
+            return first(a);
+        }
+        int main() {
+            int l[3];
+            return fill(l, 2) + first(g);
+        }
+    ";
+
+    static ARRAY_ACCESS_TEST: &str = "
+        int g[4];
+        int main() {
+            int l[4];
+            int i;
+            i = 2;
+            l[i] = 7;
+            g[3] = l[i] + 1;
+            return l[2] + g[3] * 10;
+        }
+    ";
+
+    static DUPLICATE_FUNCTION_TEST: &str = "
+        int f(int x) {
+            return x;
+        }
+        int f() {
+            return 0;
+        }
+    ";
+
+    static NON_FUNCTION_CALL_TEST: &str = "
+        int a[4];
+        int main() {
+            return a(1);
+        }
+    ";
+
+    static ARGUMENT_COUNT_TEST: &str = "
+        int f(int x) {
+            return f(x, x);
+        }
+    ";
+
+    static RETURN_TYPE_TEST: &str = "
+        void f() {
+            return 3;
+        }
+        int g() {
+            return;
+        }
+        int h(int x) {
+            if (x) {
+                return 1;
+            }
+            while (x) {
+                return 2;
+            }
+        }
+        int k(int x) {
+            if (x) return 1; else return 0;
+        }
+        int forever() {
+            while (1) {}
+        }
+    ";
+
+    static SYNTAX_ERROR_TEST: &str = "
+        int main() {
+            return 1
+        }
+    ";
+
+    static MULTIPLE_SYNTAX_ERRORS_TEST: &str = "
+        int f(int x) {
+            x = (x + ;
+            return x;
+        }
+        int g() {
+            g(, 1);
+            return 0;
+        }
+    ";
+
+    static MULTIPLE_ERRORS_TEST: &str = "
+        int a[4];
+        int f(int x) {
+            x = a;
+            return undeclared + undeclared * 2;
+        }
+        int g(int y) {
+            y = undeclared;
+            if (a) {
+                y = g(y, y);
+            }
+            return y(1);
+        }
+    ";
+
+    static UNFORMATTED_TEST: &str = "
+  int a[3];int f(int x){return x;}
+int main(){int b; b=((1+2)*3)-(4-5)+(a[1]=2)%2; if(b<(2==3))if(b)output(b);else output(1); else {while((b>0)) b=b-1;} return a[f(1)-(0)] ;}
+";
+
+    static FORMATTED_TEST: &str = "\
+int a[3];
+
+int f(int x) {
+    return x;
+}
+
+int main() {
+    int b;
+    b = (1 + 2) * 3 - (4 - 5) + (a[1] = 2) % 2;
+    if (b < (2 == 3))
+        if (b)
+            output(b);
+        else
+            output(1);
+    else {
+        while (b > 0)
+            b = b - 1;
+    }
+    return a[f(1) - 0];
+}
+";
+
+    static COMMENTS_TEST: &str = "
+        /* The sum of
+           the first n numbers. */
+        int sum(int n) { // n >= 0
+            int s; /* s starts out as 0 */
+            while (n > 0) {
+                s = s + n; n = n - 1;
+                // the loop ends at 0
+            } // end while
+            return s /* the sum */;
+        }
+        // main /* does not open a block comment here
+        int main() { return sum(/* n = */ 10); }
+    ";
+
+    static FORMATTED_COMMENTS_TEST: &str = "\
+/* The sum of
+           the first n numbers. */
+int sum(int n) {
+    // n >= 0
+    int s; /* s starts out as 0 */
+    while (n > 0) {
+        s = s + n;
+        n = n - 1;
+        // the loop ends at 0
+    } // end while
+    return s; /* the sum */
+}
+
+// main /* does not open a block comment here
+int main() {
+    return sum(10); /* n = */
+}
+";
+
+    static SPACED_TOKENS_TEST: &str = "
+        int max_value [ 4 ] ;
+        void set_max(int values [ ], int new_value) {
+            max_value [ 0 ] = values[ 1 ] ;
+            return ;
+        }
+        int main ( ) { return max_value[0]; }
+    ";
+
+    static LEXICAL_ERRORS_TEST: &str = "
+        int main() {
+            int x;
+            x = 2147483648 + 2147483647;
+            return x @ 1 # 2;
+        }
+    ";
+
+    static COMMENT_ERRORS_TEST: &str = "
+        int main() {
+            /* one /* two */
+            return 0;
+        }
+        /* never closed
+        int f() { return 1; }
+    ";
+
+    static ARRAY_VALUE_TEST: &str = "
+        int a[3];
+        int b[3];
+        int f(int p[]) {
+            p;
+            return 0;
+        }
+        int main() {
+            a = b;
+            a;
+            return 0;
+        }
+    ";
+
+    fn typecheck_failures(source: &str) -> Vec<Diagnostic> {
+        let program = parse_program(source).unwrap();
+        match typecheck_program(&program) {
+            TypecheckProgramResult::Failure(diagnostics) => diagnostics,
+            TypecheckProgramResult::Success => panic!("expected a type error"),
+        }
+    }
+
+    fn typecheck_failure(source: &str) -> Diagnostic {
+        typecheck_failures(source).remove(0)
+    }
+
+    fn parse_expression(source: &str) -> Box<Expression> {
+        let tokens = lex(source).unwrap().tokens;
+        grammar::ExpressionParser::new()
+            .parse(&mut Vec::new(), tokens.into_iter().map(Ok))
+            .unwrap()
+    }
+
+    #[test]
+    fn typecheck_error_location() {
+        let diagnostic = typecheck_failure(UNDECLARED_CALL_TEST);
+        assert_eq!(diagnostic.code, Code::UndeclaredIdentifier);
+        let source = SourceFile::new("undeclared.k", UNDECLARED_CALL_TEST);
+        assert_eq!(source.line_col(diagnostic.span.start), (4, 9));
+        assert_eq!(
+            diagnostic.render(&source),
+            "undeclared.k:4:9: error[E0006]: call of undeclared function `missing`\n    a = missing(a);\n        ^^^^^^^^^^"
+        );
+    }
+
+    #[test]
+    fn typecheck_error_codes() {
+        let diagnostic = typecheck_failure(DUPLICATE_DECLARATION_TEST);
+        assert_eq!(diagnostic.code, Code::DuplicateDeclaration);
+        assert_eq!(diagnostic.labels.len(), 1);
+        assert_eq!(typecheck_failure(NON_FUNCTION_CALL_TEST).code, Code::CallOfNonFunction);
+        assert_eq!(typecheck_failure(ARGUMENT_COUNT_TEST).code, Code::ArgumentCountMismatch);
+    }
+
+    #[test]
+    fn calls_between_functions() {
+        let program = parse_program(MUTUAL_RECURSION_TEST).unwrap();
+        assert_eq!(typecheck_program(&program), TypecheckProgramResult::Success);
+        codegen(&program, &SourceFile::new("calls_between_functions", MUTUAL_RECURSION_TEST), &CodegenOptions::default()).unwrap();
+        let diagnostic = typecheck_failure(DUPLICATE_FUNCTION_TEST);
+        assert_eq!(diagnostic.code, Code::DuplicateDeclaration);
+        assert_eq!(diagnostic.labels.len(), 1);
+    }
+
+    #[test]
+    fn globals_in_function_bodies() {
+        let program = parse_program(GLOBALS_TEST).unwrap();
+        assert_eq!(typecheck_program(&program), TypecheckProgramResult::Success);
+        codegen(&program, &SourceFile::new("globals_in_function_bodies", GLOBALS_TEST), &CodegenOptions::default()).unwrap();
+    }
+
+    #[test]
+    fn array_parameters() {
+        let program = parse_program(ARRAY_PARAMETER_TEST).unwrap();
+        assert_eq!(typecheck_program(&program), TypecheckProgramResult::Success);
+        codegen(&program, &SourceFile::new("array_parameters", ARRAY_PARAMETER_TEST), &CodegenOptions::default()).unwrap();
+    }
+
+    #[test]
+    fn array_element_access() {
+        let program = parse_program(ARRAY_ACCESS_TEST).unwrap();
+        assert_eq!(typecheck_program(&program), TypecheckProgramResult::Success);
+        codegen(&program, &SourceFile::new("array_element_access", ARRAY_ACCESS_TEST), &CodegenOptions::default()).unwrap();
+        let options = CodegenOptions {
+            bounds_checks: true,
+            ..CodegenOptions::default()
+        };
+        codegen(&program, &SourceFile::new("array_element_access", ARRAY_ACCESS_TEST), &options).unwrap();
+        let program = parse_program(ARRAY_PARAMETER_TEST).unwrap();
+        codegen(&program, &SourceFile::new("array_parameters", ARRAY_PARAMETER_TEST), &options).unwrap();
+    }
+
+    #[test]
+    fn typecheck_reports_every_error() {
+        let codes: Vec<Code> = typecheck_failures(MULTIPLE_ERRORS_TEST)
+            .iter()
+            .map(|diagnostic| diagnostic.code)
+            .collect();
+        assert_eq!(
+            codes,
+            vec![
+                Code::InvalidAssignment,
+                Code::UndeclaredIdentifier,
+                Code::UndeclaredIdentifier,
+                Code::NonIntCondition,
+                Code::ArgumentCountMismatch,
+                Code::CallOfNonFunction,
+            ]
+        );
+    }
+
+    #[test]
+    fn arrays_are_not_values() {
+        let diagnostics = typecheck_failures(ARRAY_VALUE_TEST);
+        let source = SourceFile::new("arrays.k", ARRAY_VALUE_TEST);
+        let errors: Vec<(Code, String)> = diagnostics
+            .iter()
+            .map(|diagnostic| (diagnostic.code, source.location(diagnostic.span)))
+            .collect();
+        assert_eq!(
+            errors,
+            vec![
+                (Code::ArrayUsedAsValue, "arrays.k:5:13".to_string()),
+                (Code::InvalidAssignment, "arrays.k:9:13".to_string()),
+                (Code::ArrayUsedAsValue, "arrays.k:10:13".to_string()),
+            ]
+        );
+        assert_eq!(diagnostics[1].message, "cannot assign to `a`");
+        assert_eq!(source.location(diagnostics[1].labels[0].span), "arrays.k:2:9");
+    }
+
+    #[test]
+    fn return_type_checking() {
+        let diagnostics = typecheck_failures(RETURN_TYPE_TEST);
+        let source = SourceFile::new("returns.k", RETURN_TYPE_TEST);
+        let errors: Vec<(Code, String)> = diagnostics
+            .iter()
+            .map(|diagnostic| (diagnostic.code, source.location(diagnostic.span)))
+            .collect();
+        assert_eq!(
+            errors,
+            vec![
+                (Code::InvalidReturnValue, "returns.k:3:20".to_string()),
+                (Code::InvalidReturnValue, "returns.k:6:13".to_string()),
+                (Code::MissingReturn, "returns.k:15:9".to_string()),
+            ]
+        );
+        assert_eq!(diagnostics[0].message, "cannot return a value from void function `f`");
+        assert_eq!(diagnostics[2].code.as_str(), "E0015");
+    }
+
+    #[test]
+    fn builtin_functions() {
+        let program = parse_program(BUILTINS_TEST).unwrap();
+        assert_eq!(typecheck_program(&program), TypecheckProgramResult::Success);
+        let source = SourceFile::new("builtins.k", BUILTINS_TEST);
+        let ir = codegen(&program, &source, &CodegenOptions::default()).unwrap().ir();
+        assert!(ir.contains("declare i32 @input()"));
+        assert!(ir.contains("declare void @output(i32)"));
+
+        let diagnostics = typecheck_failures(BUILTIN_MISUSE_TEST);
+        let codes: Vec<Code> = diagnostics.iter().map(|diagnostic| diagnostic.code).collect();
+        assert_eq!(
+            codes,
+            vec![
+                Code::DuplicateDeclaration,
+                Code::DuplicateDeclaration,
+                Code::ArgumentCountMismatch,
+            ]
+        );
+        assert!(diagnostics.iter().all(|diagnostic| diagnostic.labels.is_empty()));
+        assert_eq!(diagnostics[0].notes, vec!["`input` is a built-in function".to_string()]);
+    }
+
+    #[test]
+    fn typecheck_error_cap() {
+        let mut source = "int f() {\n".to_string();
+        for i in 0..MAX_ERRORS + 5 {
+            source.push_str(&format!("    undeclared{} = 1;\n", i));
+        }
+        source.push_str("}\n");
+        let diagnostics = typecheck_failures(&source);
+        assert_eq!(diagnostics.len(), MAX_ERRORS);
+        assert_eq!(diagnostics[MAX_ERRORS - 1].notes.len(), 1);
+    }
+
+    #[test]
+    fn syntax_error_diagnostic() {
+        let diagnostic = &parse_program(SYNTAX_ERROR_TEST).unwrap_err()[0];
+        assert_eq!(diagnostic.code, Code::SyntaxError);
+        assert_eq!(diagnostic.code.as_str(), "E0001");
+        assert_eq!(diagnostic.message, "expected `;` or an operator but found `}`");
+        let diagnostic = &parse_program("int a }").unwrap_err()[0];
+        assert_eq!(diagnostic.message, "expected `(`, `;` or `[` but found `}`");
+        let diagnostic = &parse_program("int a int main() { return 0; }").unwrap_err()[0];
+        assert_eq!(diagnostic.message, "expected `(`, `;` or `[` but found `int`");
+    }
+
+    #[test]
+    fn syntax_error_recovery() {
+        let diagnostics = parse_program(MULTIPLE_SYNTAX_ERRORS_TEST).unwrap_err();
+        let source = SourceFile::new("test.c", MULTIPLE_SYNTAX_ERRORS_TEST);
+        let locations: Vec<String> = diagnostics
+            .iter()
+            .map(|diagnostic| source.location(diagnostic.span))
+            .collect();
+        assert_eq!(locations, vec!["test.c:3:22", "test.c:7:15"]);
+        assert!(diagnostics[1].message.ends_with("but found `,`"));
+
+        // The block after a broken condition is parsed, so its `}` does not
+        // end the function early.
+        let diagnostics = parse_program("int main() {\n    if (1 { return 1; }\n    return 0;\n}").unwrap_err();
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].message, "expected `)` or an operator but found `{`");
+
+        let source = format!("int main() {{ int x; {} return 0; }}", "x = ;".repeat(MAX_ERRORS + 5));
+        let diagnostics = parse_program(&source).unwrap_err();
+        assert_eq!(diagnostics.len(), MAX_ERRORS);
+        assert_eq!(diagnostics[MAX_ERRORS - 1].notes, vec!["stopped reporting after 20 errors; 5 more not shown"]);
+    }
+
     #[test]
     fn dangling_else() {
-        let program = grammar::ProgramParser::new()
-            .parse(DANGLING_ELSE)
+        let program = parse_program(DANGLING_ELSE)
             .unwrap();
         let is_typed_correctly = typecheck_program(&program);
         assert_eq!(
             is_typed_correctly,
             TypecheckProgramResult::Success
         );
-        codegen(&program, "dangling_else");
+        codegen(&program, &SourceFile::new("dangling_else", DANGLING_ELSE), &CodegenOptions::default()).unwrap();
         assert_eq!(
-            program.fun_declarations[0].body.statements[0],
-            Box::new(Statement::IfStatement(Box::new(IfStatement::IfStmt(
+            without_spans(&program.fun_declarations[0].body.statements[0]),
+            without_spans(&Box::new(Statement::IfStatement(Box::new(IfStatement::IfStmt(
                 Box::new(Expression::Var(
-                    Box::new(Var::Var("a".to_string()))
+                    Box::new(Var::Var("a".to_string(), Span::default()))
                 )),
                 Box::new(Statement::IfStatement(Box::new(IfStatement::IfElseStmt(
                     Box::new(Expression::Var(
-                        Box::new(Var::Var("b".to_string()))
+                        Box::new(Var::Var("b".to_string(), Span::default()))
                     )),
-                    Box::new(Statement::EmptyStatement),
-                    Box::new(Statement::EmptyStatement)
-                ))))
-        )))));
+                    Box::new(Statement::EmptyStatement(Span::default())),
+                    Box::new(Statement::EmptyStatement(Span::default())),
+                    Span::default()
+                )))),
+                Span::default()
+        ))))));
     }
 
     #[test]
     fn assignment() {
-        let program = grammar::ProgramParser::new()
-            .parse(ASSIGNMENT_TEST)
+        let program = parse_program(ASSIGNMENT_TEST)
             .unwrap();
-        codegen(&program, "assignment");
+        codegen(&program, &SourceFile::new("assignment", ASSIGNMENT_TEST), &CodegenOptions::default()).unwrap();
+        let source = SourceFile::new("assignment", ASSIGNMENT_TEST);
+        assert_eq!(source.location(program.fun_declarations[0].body.statements[0].span()), "assignment:4:13");
         assert_eq!(
-            program.fun_declarations[0].body.statements[0],
-            Box::new(Statement::Expression(Box::new(Expression::Assignment(
-                Box::new(Var::Var("a".to_string())),
-                Box::new(Expression::IntegerLiteral(5))
-            ))))
+            without_spans(&program.fun_declarations[0].body.statements[0]),
+            without_spans(&Box::new(Statement::Expression(Box::new(Expression::Assignment(
+                Box::new(Var::Var("a".to_string(), Span::default())),
+                Box::new(Expression::IntegerLiteral(5, Span::default())),
+                Span::default()
+            )))))
         );
     }
 
     #[test]
     fn while_statement() {
-        let program = grammar::ProgramParser::new().parse(WHILE_TEST).unwrap();
-        codegen(&program, "while_statement");
+        let program = parse_program(WHILE_TEST).unwrap();
+        codegen(&program, &SourceFile::new("while_statement", WHILE_TEST), &CodegenOptions::default()).unwrap();
         assert_eq!(
-            program.fun_declarations[0].body.statements[0],
-            Box::new(Statement::WhileStatement(Box::new(WhileStatement {
-                condition: Box::new(Expression::Var(Box::new(Var::Var("a".to_string())))),
+            without_spans(&program.fun_declarations[0].body.statements[0]),
+            without_spans(&Box::new(Statement::WhileStatement(Box::new(WhileStatement {
+                condition: Box::new(Expression::Var(Box::new(Var::Var("a".to_string(), Span::default())))),
                 statement: Box::new(Statement::CompoundStatement(Box::new(CompoundStatement {
                     declarations: Vec::new(),
-                    statements: Vec::new()
-                })))
-            })))
+                    statements: Vec::new(),
+                    span: Span::default()
+                }))),
+                span: Span::default()
+            }))))
         );
     }
     #[test]
     fn if_statement() {
-        let program = grammar::ProgramParser::new().parse(IF_TEST).unwrap();
-        codegen(&program, "if_statement");
+        let program = parse_program(IF_TEST).unwrap();
+        codegen(&program, &SourceFile::new("if_statement", IF_TEST), &CodegenOptions::default()).unwrap();
         assert_eq!(
-            *program.fun_declarations[0].body.statements[0],
-            Statement::IfStatement(Box::new(IfStatement::IfElseStmt(
-                Box::new(Expression::Var(Box::new(Var::Var("a".to_string())))),
+            without_spans(&*program.fun_declarations[0].body.statements[0]),
+            without_spans(&Statement::IfStatement(Box::new(IfStatement::IfElseStmt(
+                Box::new(Expression::Var(Box::new(Var::Var("a".to_string(), Span::default())))),
                 Box::new(Statement::CompoundStatement(Box::new(CompoundStatement {
                     declarations: Vec::new(),
-                    statements: vec![Box::new(Statement::ReturnStatement(Option::Some(Box::new(
-                        Expression::IntegerLiteral(0)
-                    ))))]
+                    statements: vec![Box::new(Statement::ReturnStatement(
+                        Option::Some(Box::new(Expression::IntegerLiteral(0, Span::default()))),
+                        Span::default()
+                    ))],
+                    span: Span::default()
                 }))),
                 Box::new(Statement::CompoundStatement(Box::new(CompoundStatement {
                     declarations: Vec::new(),
-                    statements: vec![Box::new(Statement::ReturnStatement(Option::Some(Box::new(
-                        Expression::IntegerLiteral(1)
-                    ))))]
+                    statements: vec![Box::new(Statement::ReturnStatement(
+                        Option::Some(Box::new(Expression::IntegerLiteral(1, Span::default()))),
+                        Span::default()
+                    ))],
+                    span: Span::default()
                 }))),
-            )))
+                Span::default()
+            ))))
         );
     }
 
     #[test]
     fn var_declarations() {
-        let program = grammar::ProgramParser::new()
-            .parse(VAR_DECLARATIONS_TEST)
+        let program = parse_program(VAR_DECLARATIONS_TEST)
             .unwrap();
-        // codegen(&program, "var_declarations");
+        // codegen(&program, &SourceFile::new("var_declarations", VAR_DECLARATIONS_TEST), &CodegenOptions::default());
         assert_eq!(
-            program.var_declarations[0],
-            VarDeclaration::VarDeclaration(IdentifierType::Int, "test".to_string())
+            without_spans(&program.var_declarations[0]),
+            without_spans(&VarDeclaration::VarDeclaration(IdentifierType::Int, "test".to_string(), Span::default()))
         );
         assert_eq!(
-            program.var_declarations[1],
-            VarDeclaration::ArrDeclaration(IdentifierType::Int, "test2".to_string(), 4)
+            without_spans(&program.var_declarations[1]),
+            without_spans(&VarDeclaration::ArrDeclaration(
+                IdentifierType::Int,
+                "test2".to_string(),
+                4,
+                Span::default()
+            ))
         );
         assert_eq!(program.fun_declarations.len(), 0)
     }
 
     #[test]
     fn expression() {
-        let expression = grammar::ExpressionParser::new()
-            .parse(EXPRESSION_TEST)
-            .unwrap();
+        let expression = parse_expression(EXPRESSION_TEST);
         use Expression::*;
+        assert_eq!(expression.span(), Span::new(0, 18));
+        match &*expression {
+            Operation(lhs, _, _, _) => assert_eq!(lhs.span(), Span::new(0, 7)),
+            _ => panic!("expected an operation"),
+        }
         assert_eq!(
-            *expression,
-            Operation(
+            without_spans(&*expression),
+            without_spans(&Operation(
                 Box::new(Operation(
-                    Box::new(IntegerLiteral(0)),
+                    Box::new(IntegerLiteral(0, Span::default())),
                     Operator::Mul,
                     Box::new(Operation(
-                        Box::new(IntegerLiteral(1)),
+                        Box::new(IntegerLiteral(1, Span::default())),
                         Operator::Add,
-                        Box::new(IntegerLiteral(2))
-                    ))
+                        Box::new(IntegerLiteral(2, Span::default())),
+                        Span::default()
+                    )),
+                    Span::default()
                 )),
                 Operator::Add,
                 Box::new(Operation(
                     Box::new(Operation(
-                        Box::new(IntegerLiteral(3)),
+                        Box::new(IntegerLiteral(3, Span::default())),
                         Operator::Mul,
-                        Box::new(IntegerLiteral(4))
+                        Box::new(IntegerLiteral(4, Span::default())),
+                        Span::default()
                     )),
                     Operator::Sub,
                     Box::new(Call(Box::new(FunctionCall {
                         name: "e".to_string(),
-                        args: vec![Box::new(IntegerLiteral(1))]
-                    })))
-                ))
-            )
+                        args: vec![Box::new(IntegerLiteral(1, Span::default()))],
+                        span: Span::default()
+                    }))),
+                    Span::default()
+                )),
+                Span::default()
+            ))
         );
     }
 
     #[test]
     fn left_associative_expression() {
-        let expression = grammar::ExpressionParser::new()
-            .parse(ASSOCIATIVITY_TEST)
-            .unwrap();
+        let expression = parse_expression(ASSOCIATIVITY_TEST);
         use Expression::*;
         assert_eq!(
-            *expression,
-            Operation(
+            without_spans(&*expression),
+            without_spans(&Operation(
                 Box::new(Operation(
-                    Box::new(IntegerLiteral(0)),
+                    Box::new(IntegerLiteral(0, Span::default())),
                     Operator::Add,
-                    Box::new(IntegerLiteral(1))
+                    Box::new(IntegerLiteral(1, Span::default())),
+                    Span::default()
                 )),
                 Operator::Sub,
-                Box::new(IntegerLiteral(2))
-            )
+                Box::new(IntegerLiteral(2, Span::default())),
+                Span::default()
+            ))
+        );
+    }
+
+    #[test]
+    fn modulo_expression() {
+        let expression = parse_expression(MODULO_TEST);
+        use Expression::*;
+        assert_eq!(
+            without_spans(&*expression),
+            without_spans(&Operation(
+                Box::new(IntegerLiteral(1, Span::default())),
+                Operator::Add,
+                Box::new(Operation(
+                    Box::new(IntegerLiteral(7, Span::default())),
+                    Operator::Mod,
+                    Box::new(IntegerLiteral(2, Span::default())),
+                    Span::default()
+                )),
+                Span::default()
+            ))
+        );
+    }
+
+    #[test]
+    fn signed_arithmetic() {
+        let diagnostic = typecheck_failure(SIGNED_ARITHMETIC_TEST);
+        assert_eq!(diagnostic.code, Code::NonIntOperand);
+        assert_eq!(diagnostic.message, "operand of `%` must be an int, found an array");
+        let mut program = parse_program(SIGNED_ARITHMETIC_TEST).unwrap();
+        program.fun_declarations.pop();
+        assert_eq!(typecheck_program(&program), TypecheckProgramResult::Success);
+        let source = SourceFile::new("signed_arithmetic", SIGNED_ARITHMETIC_TEST);
+        let ir = codegen(&program, &source, &CodegenOptions::default()).unwrap().ir();
+        assert!(ir.contains("sdiv i32"));
+        assert!(ir.contains("srem i32"));
+        assert!(ir.contains("zext i1"));
+    }
+
+    #[test]
+    fn emit_object_and_assembly() {
+        let program = parse_program(MUTUAL_RECURSION_TEST).unwrap();
+        let source = SourceFile::new("emit", MUTUAL_RECURSION_TEST);
+        let module = codegen(&program, &source, &CodegenOptions::default()).unwrap();
+        let machine = TargetMachine::host(OptLevel::O0).unwrap();
+        let directory = std::env::temp_dir();
+        let object = directory.join(format!("emit-{}.o", std::process::id()));
+        let assembly = directory.join(format!("emit-{}.s", std::process::id()));
+        machine.emit(&module, object.to_str().unwrap(), OutputKind::Object).unwrap();
+        machine.emit(&module, assembly.to_str().unwrap(), OutputKind::Assembly).unwrap();
+        assert!(std::fs::metadata(&object).unwrap().len() > 0);
+        assert!(std::fs::read_to_string(&assembly).unwrap().contains("main"));
+        std::fs::remove_file(object).unwrap();
+        std::fs::remove_file(assembly).unwrap();
+    }
+
+    #[test]
+    fn link_with_runtime() {
+        let program = parse_program(BUILTINS_TEST).unwrap();
+        let source = SourceFile::new("runtime", BUILTINS_TEST);
+        let module = codegen(&program, &source, &CodegenOptions::default()).unwrap();
+        let directory = std::env::temp_dir();
+        let object = directory.join(format!("runtime-{}.o", std::process::id()));
+        let executable = directory.join(format!("runtime-{}", std::process::id()));
+        let machine = TargetMachine::host(OptLevel::O0).unwrap();
+        machine.emit(&module, object.to_str().unwrap(), OutputKind::Object).unwrap();
+        link(object.to_str().unwrap(), executable.to_str().unwrap()).unwrap();
+
+        let mut child = std::process::Command::new(&executable)
+            .stdin(std::process::Stdio::piped())
+            .stdout(std::process::Stdio::piped())
+            .spawn()
+            .unwrap();
+        child.stdin.take().unwrap().write_all(b"3\n").unwrap();
+        let result = child.wait_with_output().unwrap();
+        assert!(result.status.success());
+        assert_eq!(String::from_utf8(result.stdout).unwrap(), "9\n4\n1\n");
+        std::fs::remove_file(object).unwrap();
+        std::fs::remove_file(executable).unwrap();
+    }
+
+    #[test]
+    fn run_in_process() {
+        let program = parse_program(BUILTINS_TEST).unwrap();
+        let source = SourceFile::new("run", BUILTINS_TEST);
+        let module = codegen(&program, &source, &CodegenOptions::default()).unwrap();
+        assert_eq!(run_with_input(&module, "3\n").unwrap(), (0, "9\n4\n1\n".to_string()));
+        assert_eq!(run_with_input(&module, "-2").unwrap(), (0, String::new()));
+        assert!(run_with_input(&module, "three").is_err());
+
+        for (test, expected) in [(MUTUAL_RECURSION_TEST, 1), (GLOBALS_TEST, 3)] {
+            let program = parse_program(test).unwrap();
+            for level in [OptLevel::O0, OptLevel::O2] {
+                let module = codegen(&program, &source, &CodegenOptions::default()).unwrap();
+                optimize(&module, &TargetMachine::host(level).unwrap());
+                assert_eq!(run_with_input(&module, "").unwrap().0, expected);
+            }
+        }
+
+        let program = parse_program("void f() { }").unwrap();
+        let module = codegen(&program, &source, &CodegenOptions::default()).unwrap();
+        assert!(run_with_input(&module, "").is_err());
+    }
+
+    #[test]
+    fn bounds_checks_through_array_parameters() {
+        let text = "int get(int a[], int i) { return a[i]; }\nint main() { int a[3]; output(get(a, 2)); return get(a, 3); }";
+        let program = parse_program(text).unwrap();
+        let source = SourceFile::new("bounds", text);
+        let options = CodegenOptions {
+            bounds_checks: true,
+            ..CodegenOptions::default()
+        };
+        for level in [OptLevel::O0, OptLevel::O2] {
+            let module = codegen(&program, &source, &options).unwrap();
+            optimize(&module, &TargetMachine::host(level).unwrap());
+            // The trap unwinds out of the program instead of exiting the tests.
+            assert_eq!(
+                run_with_input(&module, "").unwrap_err(),
+                "bounds:1:34: index 3 out of bounds for array `a` of length 3"
+            );
+        }
+    }
+
+    fn interpret_source(source: &str, input: &str, limits: Limits) -> Result<(i32, String), RuntimeError> {
+        let program = parse_program(source).unwrap();
+        assert_eq!(typecheck_program(&program), TypecheckProgramResult::Success);
+        let mut output = Vec::new();
+        let result = interpret(&program, limits, &mut input.as_bytes(), &mut output)?;
+        Ok((result, String::from_utf8(output).unwrap()))
+    }
+
+    #[test]
+    fn interpreter() {
+        let limits = Limits::default();
+        assert_eq!(interpret_source(BUILTINS_TEST, "3\n", limits).unwrap(), (0, "9\n4\n1\n".to_string()));
+        assert_eq!(interpret_source(BUILTINS_TEST, "x", limits).unwrap_err().kind, ErrorKind::InvalidInput);
+
+        let source = SourceFile::new("interpreter", "");
+        for (test, expected) in [(MUTUAL_RECURSION_TEST, 1), (GLOBALS_TEST, 3), (ARRAY_ACCESS_TEST, 87)] {
+            assert_eq!(interpret_source(test, "", limits).unwrap().0, expected);
+            let module = codegen(&parse_program(test).unwrap(), &source, &CodegenOptions::default()).unwrap();
+            assert_eq!(run_with_input(&module, "").unwrap().0, expected);
+        }
+
+        assert_eq!(interpret_source(RUNTIME_ERRORS_TEST, "0", limits).unwrap().0, -32768);
+        let small = Limits { max_steps: 10_000, max_depth: 100 };
+        for (input, kind) in [
+            ("1", ErrorKind::IndexOutOfBounds),
+            ("2", ErrorKind::DivisionByZero),
+            ("3", ErrorKind::Overflow),
+            ("4", ErrorKind::StepLimit),
+            ("5", ErrorKind::DepthLimit),
+        ] {
+            assert_eq!(interpret_source(RUNTIME_ERRORS_TEST, input, small).unwrap_err().kind, kind);
+        }
+        let error = interpret_source(RUNTIME_ERRORS_TEST, "1", limits).unwrap_err();
+        let source = SourceFile::new("errors", RUNTIME_ERRORS_TEST);
+        assert!(error.render(&source).starts_with("errors:3:20: runtime error: index 2 out of bounds for array `a` of length 2"));
+        // The default depth limit is reached before the host stack runs out.
+        assert_eq!(interpret_source(RUNTIME_ERRORS_TEST, "5", limits).unwrap_err().kind, ErrorKind::DepthLimit);
+
+        let error = interpret_source("int f(int x) { return x; }", "", limits).unwrap_err();
+        assert_eq!(error.kind, ErrorKind::InvalidMain);
+
+        // Typecheck rejects these, but the interpreter must not panic on them.
+        let program = parse_program(ARRAY_VALUE_TEST).unwrap();
+        let error = interpret(&program, limits, &mut "".as_bytes(), &mut Vec::new()).unwrap_err();
+        assert_eq!(error.kind, ErrorKind::Internal);
+        assert_eq!(error.message, "internal error: array `b` used as an int");
+        let program = parse_program("int a[3]; int main() { a; return 0; }").unwrap();
+        let error = interpret(&program, limits, &mut "".as_bytes(), &mut Vec::new()).unwrap_err();
+        assert_eq!(error.message, "internal error: array `a` used as an int");
+    }
+
+    #[test]
+    fn differential_corpus() {
+        let corpus = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("corpus");
+        match run_corpus(&corpus) {
+            Ok(summary) => {
+                assert!(summary.agreed >= 10);
+                assert_eq!(summary.skipped, vec![]);
+            }
+            Err(report) => panic!("{}", report),
+        }
+
+        let verdict = compare("divide", "int main() { int z; return 1 / z; }", "").unwrap();
+        assert!(matches!(verdict, Verdict::Skipped(_)));
+        assert!(compare("broken", "int main() { return x; }", "").is_err());
+    }
+
+    #[test]
+    fn random_programs() {
+        // FUZZ_SEEDS=1000 cargo test random_programs tries more programs.
+        let seeds = std::env::var("FUZZ_SEEDS").ok().and_then(|seeds| seeds.parse().ok()).unwrap_or(20);
+        match fuzz(0..seeds) {
+            Ok(summary) => assert_eq!(summary.skipped, vec![]),
+            Err(report) => panic!("{}", report),
+        }
+
+        let (program, input) = generate(7);
+        assert_eq!(generate(7), (program, input));
+        let source = print_program(&generate(7).0);
+        assert_eq!(print_program(&parse_program(&source).unwrap()), source);
+    }
+
+    #[test]
+    fn formatter() {
+        let program = parse_program(UNFORMATTED_TEST).unwrap();
+        assert_eq!(print_program(&program), FORMATTED_TEST);
+        assert_eq!(without_spans(&parse_program(FORMATTED_TEST).unwrap()), without_spans(&program));
+        assert_eq!(print_program(&parse_program(FORMATTED_TEST).unwrap()), FORMATTED_TEST);
+
+        let source = "int main() { return 1 - (2 - 3) * 4 / (5 % 6) - 7 + (8 > 9 > 10) + (11 == (12 < 13)); }";
+        let expected = "int main() {\n    return 1 - (2 - 3) * 4 / (5 % 6) - 7 + (8 > 9 > 10) + (11 == (12 < 13));\n}\n";
+        assert_eq!(print_program(&parse_program(source).unwrap()), expected);
+        let source = "int main() { int x; return x = 1 < 2 - 3 * 4 == 5; }";
+        let expected = "int main() {\n    int x;\n    return x = 1 < 2 - 3 * 4 == 5;\n}\n";
+        assert_eq!(print_program(&parse_program(source).unwrap()), expected);
+    }
+
+    #[test]
+    fn comments() {
+        let (program, comments) = parse_with_comments(COMMENTS_TEST).unwrap();
+        assert_eq!(typecheck_program(&program), TypecheckProgramResult::Success);
+        let texts: Vec<&str> = comments.iter().map(|comment| comment.text).collect();
+        assert_eq!(texts[1], "// n >= 0");
+        assert_eq!(texts[6], "// main /* does not open a block comment here");
+        assert_eq!(texts.len(), 8);
+
+        let formatted = format_program(&program, COMMENTS_TEST, &comments);
+        assert_eq!(formatted, FORMATTED_COMMENTS_TEST);
+        let (reparsed, comments) = parse_with_comments(&formatted).unwrap();
+        assert_eq!(without_spans(&reparsed), without_spans(&program));
+        assert_eq!(format_program(&reparsed, &formatted, &comments), formatted);
+
+        let diagnostics = parse_program(COMMENT_ERRORS_TEST).unwrap_err();
+        let source = SourceFile::new("comments.k", COMMENT_ERRORS_TEST);
+        let errors: Vec<(Code, String)> = diagnostics
+            .iter()
+            .map(|diagnostic| (diagnostic.code, source.location(diagnostic.span)))
+            .collect();
+        assert_eq!(
+            errors,
+            vec![
+                (Code::NestedComment, "comments.k:3:20".to_string()),
+                (Code::UnterminatedComment, "comments.k:6:9".to_string()),
+            ]
         );
+        assert_eq!(source.location(diagnostics[0].labels[0].span), "comments.k:3:13");
+        assert_eq!(diagnostics[1].message, "unterminated block comment");
+        assert_eq!(diagnostics[1].code.as_str(), "E0017");
+    }
+
+    #[test]
+    fn optimization_levels() {
+        let program = parse_program(MUTUAL_RECURSION_TEST).unwrap();
+        let source = SourceFile::new("opt.c", MUTUAL_RECURSION_TEST);
+        let unoptimized = codegen(&program, &source, &CodegenOptions::default()).unwrap();
+        optimize(&unoptimized, &TargetMachine::host(OptLevel::O0).unwrap());
+        assert!(unoptimized.ir().contains("alloca"));
+        assert!(unoptimized.ir().contains("target datalayout"));
+        for level in [OptLevel::O1, OptLevel::O2, OptLevel::O3, OptLevel::Os] {
+            let module = codegen(&program, &source, &CodegenOptions::default()).unwrap();
+            optimize(&module, &TargetMachine::host(level).unwrap());
+            assert!(!module.ir().contains("alloca"), "{:?} left an alloca", level);
+        }
+    }
+
+    #[test]
+    fn verifier_reports_internal_errors() {
+        let program = parse_program(GLOBALS_TEST).unwrap();
+        let source = SourceFile::new("verify.c", GLOBALS_TEST);
+        let module = codegen(&program, &source, &CodegenOptions::default()).unwrap();
+        assert!(module.verify().is_ok());
+        // A block without a terminator, as a codegen bug would leave it.
+        unsafe {
+            use llvm_sys::core::*;
+            let context = LLVMGetModuleContext(module.module());
+            let function_type = LLVMFunctionType(LLVMVoidTypeInContext(context), std::ptr::null_mut(), 0, 0);
+            let function = LLVMAddFunction(module.module(), c"broken".as_ptr(), function_type);
+            LLVMAppendBasicBlockInContext(context, function, c"entry".as_ptr());
+        }
+        let diagnostic = module.verify().unwrap_err();
+        assert_eq!(diagnostic.code, Code::InternalError);
+        assert!(diagnostic.message.starts_with("internal compiler error"));
+        assert!(diagnostic.notes.iter().any(|note| note.contains("define void @broken()")));
+
+        let program = parse_program(MUTUAL_RECURSION_TEST).unwrap();
+        let source = SourceFile::new("verify.c", MUTUAL_RECURSION_TEST);
+        let module = codegen(&program, &source, &CodegenOptions::default()).unwrap();
+        let function = &program.fun_declarations[1];
+        assert!(module.verify_function(function).is_ok());
+        unsafe {
+            use llvm_sys::core::*;
+            let context = LLVMGetModuleContext(module.module());
+            let name = std::ffi::CString::new(function.function_name.as_str()).unwrap();
+            let value = LLVMGetNamedFunction(module.module(), name.as_ptr());
+            LLVMAppendBasicBlockInContext(context, value, c"unterminated".as_ptr());
+        }
+        let diagnostic = module.verify_function(function).unwrap_err();
+        assert_eq!(diagnostic.message, format!("internal compiler error: LLVM rejected the code generated for function `{}`", function.function_name));
+        assert_eq!(source.location(diagnostic.span), source.location(function.span));
+        assert!(module.verify_function(&program.fun_declarations[0]).is_ok());
+    }
+
+    #[test]
+    fn debug_info() {
+        let program = parse_program(GLOBALS_TEST).unwrap();
+        let source = SourceFile::new("debug.c", GLOBALS_TEST);
+        let options = CodegenOptions {
+            debug_info: true,
+            ..CodegenOptions::default()
+        };
+        let ir = codegen(&program, &source, &options).unwrap().ir();
+        assert!(ir.contains("!DICompileUnit(language: DW_LANG_C"));
+        assert!(ir.contains("!DIFile(filename: \"debug.c\""));
+        assert!(ir.contains("!DISubprogram(name: \"bump\""));
+        assert!(ir.contains("!DIGlobalVariable(name: \"values\""));
+        assert!(ir.contains("call void @bump(), !dbg"));
+        let body = &ir[ir.find("define i32 @main").unwrap()..];
+        let body = &body[..body.find("\n}").unwrap()];
+        for line in body.lines().skip(1).filter(|line| line.starts_with("  ")) {
+            assert!(line.contains("!dbg"), "no location on `{}`", line.trim());
+        }
+
+        let ir = codegen(&program, &source, &CodegenOptions::default()).unwrap().ir();
+        assert!(!ir.contains("!dbg"));
+    }
+
+    #[test]
+    fn parallel_codegen() {
+        let programs = [MUTUAL_RECURSION_TEST, GLOBALS_TEST, ARRAY_PARAMETER_TEST, ARRAY_ACCESS_TEST];
+        let handles: Vec<_> = programs
+            .into_iter()
+            .enumerate()
+            .map(|(i, text)| {
+                std::thread::spawn(move || {
+                    let program = parse_program(text).unwrap();
+                    let source = SourceFile::new(&format!("parallel{}", i), text);
+                    let options = CodegenOptions {
+                        bounds_checks: i % 2 == 0,
+                        debug_info: i % 3 == 0,
+                    };
+                    let module = codegen(&program, &source, &options).unwrap();
+                    module.ir()
+                })
+            })
+            .collect();
+        for handle in handles {
+            assert!(handle.join().unwrap().contains("define i32 @main()"));
+        }
     }
 
     #[test]
     fn function_declarations() {
-        let program = grammar::ProgramParser::new()
-            .parse(FUN_DECLARATIONS_TEST)
+        let program = parse_program(FUN_DECLARATIONS_TEST)
             .unwrap();
         assert_eq!(program.var_declarations.len(), 0);
         assert_eq!(
-            program.fun_declarations[0],
-            FunctionDeclaration {
+            without_spans(&program.fun_declarations[0]),
+            without_spans(&FunctionDeclaration {
                 return_type: IdentifierType::Int,
                 function_name: "ident".to_string(),
                 params: vec![Param::Var(
                     IdentifierType::Int,
-                    "ab".to_string()
+                    "ab".to_string(),
+                    Span::default()
                 )],
                 body: Box::new(CompoundStatement {
                     declarations: Vec::new(),
-                    statements: vec![]
-                })
-            }
+                    statements: vec![],
+                    span: Span::default()
+                }),
+                span: Span::default()
+            })
         );
         assert_eq!(
-            program.fun_declarations[1],
-            FunctionDeclaration {
+            without_spans(&program.fun_declarations[1]),
+            without_spans(&FunctionDeclaration {
                 return_type: IdentifierType::Void,
                 function_name: "ident2".to_string(),
                 params: vec![
-                    Param::Var(IdentifierType::Int, "a".to_string()),
+                    Param::Var(IdentifierType::Int, "a".to_string(), Span::default()),
                     Param::Var(
                         IdentifierType::Int,
-                        "b".to_string()
+                        "b".to_string(),
+                        Span::default()
                     ),
                 ],
                 body: Box::new(CompoundStatement {
                     declarations: Vec::new(),
-                    statements: vec![]
-                })
-            }
+                    statements: vec![],
+                    span: Span::default()
+                }),
+                span: Span::default()
+            })
+        );
+    }
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|arg| arg.to_string()).collect()
+    }
+
+    #[test]
+    fn command_line_arguments() {
+        let options = parse_args(&args(&["--emit=llvm-ir", "-o", "out.ll", "main.k"])).unwrap();
+        assert_eq!(options.emit, Emit::LlvmIr);
+        assert_eq!(options.output.as_deref(), Some("out.ll"));
+        assert_eq!(options.input.as_deref(), Some("main.k"));
+        assert!(!options.codegen.bounds_checks);
+
+        let options = parse_args(&args(&["--bounds-check", "-"])).unwrap();
+        assert_eq!(options.emit, Emit::Executable);
+        assert_eq!(options.input, None);
+        assert!(options.codegen.bounds_checks);
+
+        assert_eq!(parse_args(&args(&["-S"])).unwrap().emit, Emit::Assembly);
+        assert_eq!(parse_args(&args(&["-O2"])).unwrap().opt_level, OptLevel::O2);
+        assert_eq!(parse_args(&args(&["-Os"])).unwrap().opt_level, OptLevel::Os);
+        assert!(parse_args(&args(&["-O4"])).is_err());
+        assert!(parse_args(&args(&["--emit=exe", "-o", "-"])).is_err());
+        assert!(parse_args(&args(&["--emit=wasm"])).is_err());
+        assert!(parse_args(&args(&["-o"])).is_err());
+        assert!(parse_args(&args(&["--verbose"])).is_err());
+        assert!(parse_args(&args(&["a.k", "b.k"])).is_err());
+
+        let options = parse_args(&args(&["run", "-O1", "main.k"])).unwrap();
+        assert!(options.run);
+        assert_eq!(options.opt_level, OptLevel::O1);
+        assert!(parse_args(&args(&["run"])).is_err());
+        assert!(parse_args(&args(&["run", "-o", "out", "main.k"])).is_err());
+        assert!(parse_args(&args(&["run", "--emit=asm", "main.k"])).is_err());
+        assert!(parse_args(&args(&["run", "--interpret", "main.k"])).unwrap().interpret);
+        assert!(parse_args(&args(&["--interpret", "main.k"])).is_err());
+
+        let options = parse_args(&args(&["fmt", "--check", "main.k"])).unwrap();
+        assert!(options.format && options.check);
+        assert_eq!(options.input.as_deref(), Some("main.k"));
+        assert!(parse_args(&args(&["fmt"])).unwrap().input.is_none());
+        assert!(parse_args(&args(&["fmt", "-o", "out.k", "main.k"])).is_err());
+        assert!(parse_args(&args(&["fmt", "-O2", "main.k"])).is_err());
+        assert!(parse_args(&args(&["--check", "main.k"])).is_err());
+    }
+
+    #[test]
+    fn tokens() {
+        let tokens: Vec<Token> = lex("int x_1; return; a[]; _ <= 07")
+            .unwrap()
+            .tokens
+            .into_iter()
+            .map(|(_, token, _)| token)
+            .collect();
+        use Token::*;
+        assert_eq!(
+            tokens,
+            vec![
+                Int, Identifier("x_1"), Semicolon, Return, Semicolon, Identifier("a"), LeftBracket,
+                RightBracket, Semicolon, Identifier("_"), LessEqual, Number(7)
+            ]
+        );
+        let lexed = lex("int /* a */ x; // b").unwrap();
+        assert_eq!(lexed.tokens, vec![(0, Int, 3), (12, Identifier("x"), 13), (13, Semicolon, 14)]);
+        assert_eq!(lexed.comments.len(), 2);
+        assert_eq!(lex("x /* y").unwrap_err()[0].code, Code::UnterminatedComment);
+        assert_eq!(lex("2147483647").unwrap().tokens[0].1, Number(i32::MAX));
+        assert_eq!(Return.to_string(), "return");
+        assert_eq!(NotEqual.kind(), "punctuation");
+    }
+
+    #[test]
+    fn lexer_whitespace_and_errors() {
+        let program = parse_program(SPACED_TOKENS_TEST).unwrap();
+        assert_eq!(typecheck_program(&program), TypecheckProgramResult::Success);
+        assert_eq!(program.fun_declarations[0].function_name, "set_max");
+        assert_eq!(without_spans(&program.fun_declarations[0].params[0]), without_spans(&Param::ArrVar(IdentifierType::Int, "values".to_string(), Span::default())));
+        assert_eq!(without_spans(&program.fun_declarations[0].body.statements[1]), without_spans(&Box::new(Statement::ReturnStatement(None, Span::default()))));
+
+        let diagnostics = parse_program(LEXICAL_ERRORS_TEST).unwrap_err();
+        let source = SourceFile::new("lexical.k", LEXICAL_ERRORS_TEST);
+        let errors: Vec<(Code, String)> = diagnostics
+            .iter()
+            .map(|diagnostic| (diagnostic.code, source.location(diagnostic.span)))
+            .collect();
+        assert_eq!(
+            errors,
+            vec![
+                (Code::LiteralOutOfRange, "lexical.k:4:17".to_string()),
+                (Code::InvalidCharacter, "lexical.k:5:22".to_string()),
+                (Code::InvalidCharacter, "lexical.k:5:26".to_string()),
+            ]
         );
+        assert_eq!(diagnostics[0].message, "integer literal `2147483648` is out of range");
+        assert_eq!(diagnostics[1].message, "unexpected character `@`");
+        assert_eq!(diagnostics[2].code.as_str(), "E0019");
+        assert_eq!(lex("é").unwrap_err()[0].span.end, 2);
     }
 }
@@ -1,529 +1,661 @@
-use crate::ast::{Expression, FunctionCall, IdentifierType, Var, VarDeclaration};
+use crate::ast::{Expression, FunctionCall, IdentifierType, Operator, Var, VarDeclaration};
+use crate::diagnostic::{Code, Diagnostic};
+use crate::runtime::{builtin, BUILTINS};
+use crate::span::Span;
 use crate::symbol_table::{
     get_child_table, ArraySymbolTableElement, ExpressionType, FunctionSymbolTableElement, Param,
     ParameterArraySymbolTableElement, SymbolTable, SymbolTableElement, VariableSymbolTableElement,
 };
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::sync::Arc;
 
+/// Errors past this many are counted but not reported.
+pub const MAX_ERRORS: usize = 20;
+
 #[derive(PartialEq, Debug)]
 pub enum TypecheckProgramResult {
     Success,
-    Failure(String),
+    Failure(Vec<Diagnostic>),
+}
+
+struct TypecheckContext {
+    diagnostics: Vec<Diagnostic>,
+    suppressed: usize,
+    // Undeclared names already reported in the current function.
+    undeclared: HashSet<String>,
+    // Signature of the function whose body is being checked.
+    return_type: IdentifierType,
+    function_name: String,
+    function_span: Span,
+}
+
+impl TypecheckContext {
+    fn report(&mut self, diagnostic: Diagnostic) {
+        if self.diagnostics.len() < MAX_ERRORS {
+            self.diagnostics.push(diagnostic);
+        } else {
+            self.suppressed += 1;
+        }
+    }
+
+    fn report_undeclared(&mut self, name: &str, diagnostic: Diagnostic) {
+        if self.undeclared.insert(name.to_string()) {
+            self.report(diagnostic);
+        }
+    }
 }
 
 pub fn typecheck_program(program: &crate::ast::Program) -> TypecheckProgramResult {
+    let mut context = TypecheckContext {
+        diagnostics: Vec::new(),
+        suppressed: 0,
+        undeclared: HashSet::new(),
+        return_type: IdentifierType::Void,
+        function_name: String::new(),
+        function_span: Span::default(),
+    };
+    handle_program(program, &mut context);
+    if context.diagnostics.is_empty() {
+        return TypecheckProgramResult::Success;
+    }
+    if context.suppressed > 0 {
+        let note = format!(
+            "stopped reporting after {} errors; {} more not shown",
+            MAX_ERRORS, context.suppressed
+        );
+        if let Some(last) = context.diagnostics.last_mut() {
+            last.notes.push(note);
+        }
+    }
+    TypecheckProgramResult::Failure(context.diagnostics)
+}
+
+fn handle_program(program: &crate::ast::Program, context: &mut TypecheckContext) {
     let mut symbol_table = SymbolTable {
         current_scope: HashMap::new(),
         parent_scope: None,
-        depth: 0,
     };
-    let was_successful = handle_variable_declarations(&program.var_declarations, &mut symbol_table);
-    if !was_successful {
-        return TypecheckProgramResult::Failure("Duplicate variable declaration".to_string());
+    for builtin in BUILTINS {
+        symbol_table.symbol_insert(
+            builtin.name,
+            SymbolTableElement::Function(FunctionSymbolTableElement {
+                return_type: builtin.return_type,
+                argument_types: builtin.params.to_vec(),
+                span: Span::default(),
+            }),
+        );
+    }
+    handle_variable_declarations(&program.var_declarations, &mut symbol_table, context);
+    for function in &program.fun_declarations {
+        insert_symbol(
+            &mut symbol_table,
+            &function.function_name,
+            function_element(function),
+            context,
+        );
     }
     let immutable_symbol_table = Arc::new(symbol_table);
     for function in &program.fun_declarations {
-        let result = handle_function(function, immutable_symbol_table.clone());
-        match result {
-            TypecheckFunctionResult::Success => {}
-            TypecheckFunctionResult::Failure(reason) => {
-                return TypecheckProgramResult::Failure(reason);
-            }
-        };
+        context.undeclared.clear();
+        context.return_type = function.return_type;
+        context.function_name = function.function_name.clone();
+        context.function_span = function.span;
+        handle_function(function, immutable_symbol_table.clone(), context);
     }
-    TypecheckProgramResult::Success
 }
 
 fn handle_variable_declarations(
-    declarations: &Vec<VarDeclaration>,
+    declarations: &[VarDeclaration],
     symbol_table: &mut SymbolTable,
-) -> bool {
+    context: &mut TypecheckContext,
+) {
     for declaration in declarations {
         let element;
         let name;
         match &declaration {
-            VarDeclaration::VarDeclaration(_, p_name) => {
+            VarDeclaration::VarDeclaration(_, p_name, span) => {
                 element = SymbolTableElement::Variable(VariableSymbolTableElement {
-                    element_type: IdentifierType::Int,
-                    depth: 0,
+                    span: *span,
                 });
                 name = p_name;
             }
-            VarDeclaration::ArrDeclaration(_, p_name, size) => {
+            VarDeclaration::ArrDeclaration(_, p_name, _, span) => {
                 element = SymbolTableElement::Array(ArraySymbolTableElement {
-                    element_type: IdentifierType::Int,
-                    size: *size,
-                    depth: 0,
+                    span: *span,
                 });
                 name = p_name;
             }
         }
-        let was_successful = symbol_table.symbol_insert(&name, element);
-        if !was_successful {
-            return false;
-        }
+        insert_symbol(symbol_table, name, element, context);
     }
-    return true;
 }
 
-pub enum TypecheckFunctionResult {
-    Success,
-    Failure(String),
+/// Inserts a symbol into the innermost scope, reporting a duplicate
+/// declaration that points back at the symbol it collides with.
+fn insert_symbol(
+    symbol_table: &mut SymbolTable,
+    name: &str,
+    element: SymbolTableElement,
+    context: &mut TypecheckContext,
+) {
+    let span = element.span();
+    if let Some(previous) = symbol_table.current_scope.get(name) {
+        let diagnostic = Diagnostic::error(
+            Code::DuplicateDeclaration,
+            &format!("duplicate declaration of `{}`", name),
+            span,
+        );
+        // Built-ins are only in the global scope and have no source to point at.
+        let diagnostic = if symbol_table.parent_scope.is_none() && builtin(name).is_some() {
+            diagnostic.with_note(&format!("`{}` is a built-in function", name))
+        } else {
+            diagnostic.with_label(previous.span(), &format!("`{}` previously declared here", name))
+        };
+        context.report(diagnostic);
+        return;
+    }
+    symbol_table.symbol_insert(name, element);
 }
 
-fn handle_function(
-    input_function: &crate::ast::FunctionDeclaration,
-    symbol_table: Arc<SymbolTable>,
-) -> TypecheckFunctionResult {
-    let element = SymbolTableElement::Function(FunctionSymbolTableElement {
-        return_type: input_function.return_type,
-        argument_types: input_function
+/// Points the diagnostic back at the declaration of `name`. Built-ins have no
+/// declaration in the file, so they get a note instead.
+fn with_declaration(diagnostic: Diagnostic, name: &str, element: &SymbolTableElement) -> Diagnostic {
+    match element {
+        SymbolTableElement::Function(_) if builtin(name).is_some() => {
+            diagnostic.with_note(&format!("`{}` is a built-in function", name))
+        }
+        _ => diagnostic.with_label(element.span(), &format!("`{}` declared here", name)),
+    }
+}
+
+/// Builds the signature that `handle_program` registers in the global scope,
+/// so every body can call every function regardless of declaration order.
+fn function_element(function: &crate::ast::FunctionDeclaration) -> SymbolTableElement {
+    SymbolTableElement::Function(FunctionSymbolTableElement {
+        return_type: function.return_type,
+        argument_types: function
             .params
             .iter()
             .map(|arg| match arg {
-                crate::ast::Param::Var(identifier_type, _) => Param::Var(*identifier_type),
-                crate::ast::Param::ArrVar(identifier_type, _) => Param::Arr(*identifier_type),
+                crate::ast::Param::Var(..) => Param::Var,
+                crate::ast::Param::ArrVar(..) => Param::Arr,
             })
             .collect(),
-        depth: 0,
-    });
-
-    let mut new_symbol_table = get_child_table(symbol_table);
-    let is_name_valid = new_symbol_table.symbol_insert(&input_function.function_name, element);
-    if !is_name_valid {
-        return TypecheckFunctionResult::Failure("Invalid function name".to_string());
-    }
+        span: function.span,
+    })
+}
 
+fn handle_function(
+    input_function: &crate::ast::FunctionDeclaration,
+    symbol_table: Arc<SymbolTable>,
+    context: &mut TypecheckContext,
+) {
+    let mut param_symbol_table = get_child_table(symbol_table);
     for param in &input_function.params {
         match param {
-            crate::ast::Param::Var(_, name) => {
-                new_symbol_table.symbol_insert(
-                    &name,
+            crate::ast::Param::Var(_, name, span) => {
+                insert_symbol(
+                    &mut param_symbol_table,
+                    name,
                     SymbolTableElement::Variable(VariableSymbolTableElement {
-                        element_type: IdentifierType::Int,
-                        depth: 1,
+                        span: *span,
                     }),
+                    context,
                 );
             }
-            crate::ast::Param::ArrVar(_, name) => {
-                new_symbol_table.symbol_insert(
-                    &name,
+            crate::ast::Param::ArrVar(_, name, span) => {
+                insert_symbol(
+                    &mut param_symbol_table,
+                    name,
                     SymbolTableElement::ParameterArray(ParameterArraySymbolTableElement {
-                        element_type: IdentifierType::Int,
-                        depth: 1,
+                        span: *span,
                     }),
+                    context,
                 );
             }
         }
     }
 
-    let immutable_symbol_table = Arc::new(new_symbol_table);
-    let body = handle_compound_statement(&input_function.body, immutable_symbol_table);
-    return match body {
-        TypecheckCompoundStatementResult::Success => TypecheckFunctionResult::Success,
-        TypecheckCompoundStatementResult::Failure(reason) => {
-            TypecheckFunctionResult::Failure(reason)
+    let immutable_symbol_table = Arc::new(param_symbol_table);
+    handle_compound_statement(&input_function.body, immutable_symbol_table, context);
+
+    if input_function.return_type == IdentifierType::Int
+        && !compound_always_returns(&input_function.body)
+    {
+        let end = input_function.body.span.end;
+        context.report(
+            Diagnostic::error(
+                Code::MissingReturn,
+                &format!(
+                    "function `{}` can reach its end without returning a value",
+                    input_function.function_name
+                ),
+                Span::new(end.saturating_sub(1), end),
+            )
+            .with_label(
+                input_function.span,
+                &format!("`{}` is declared to return int here", input_function.function_name),
+            ),
+        );
+    }
+}
+
+/// Whether every control-flow path through `statement` ends in a `return`.
+/// There is no `break`, so a loop whose condition is a non-zero literal
+/// never completes normally.
+fn always_returns(statement: &crate::ast::Statement) -> bool {
+    match statement {
+        crate::ast::Statement::ReturnStatement(_, _) => true,
+        crate::ast::Statement::CompoundStatement(compound_statement) => {
+            compound_always_returns(compound_statement)
         }
-    };
+        crate::ast::Statement::IfStatement(if_statement) => match &**if_statement {
+            crate::ast::IfStatement::IfStmt(_, _, _) => false,
+            crate::ast::IfStatement::IfElseStmt(_, statement, statement2, _) => {
+                always_returns(statement) && always_returns(statement2)
+            }
+        },
+        crate::ast::Statement::WhileStatement(while_statement) => {
+            matches!(*while_statement.condition, Expression::IntegerLiteral(value, _) if value != 0)
+        }
+        crate::ast::Statement::Expression(_) | crate::ast::Statement::EmptyStatement(_) => false,
+    }
 }
 
-pub enum TypecheckCompoundStatementResult {
-    Success,
-    Failure(String),
+fn compound_always_returns(compound_statement: &crate::ast::CompoundStatement) -> bool {
+    compound_statement.statements.iter().any(|statement| always_returns(statement))
 }
 
 fn handle_compound_statement(
     input_statement: &crate::ast::CompoundStatement,
     symbol_table: Arc<SymbolTable>,
-) -> TypecheckCompoundStatementResult {
-    match &input_statement {
-        crate::ast::CompoundStatement {
-            declarations,
-            statements,
-        } => {
-            let mut new_symbol_table = get_child_table(symbol_table.clone());
-            handle_variable_declarations(&declarations, &mut new_symbol_table);
-            let immutable_symbol_table = Arc::new(new_symbol_table);
-            for statement in statements {
-                match handle_statement(statement, immutable_symbol_table.clone()) {
-                    HandleStatementResult::Success => {}
-                    HandleStatementResult::Failure(reason) => {
-                        return TypecheckCompoundStatementResult::Failure(reason)
-                    }
-                }
-            }
-            TypecheckCompoundStatementResult::Success
-        }
+    context: &mut TypecheckContext,
+) {
+    let mut new_symbol_table = get_child_table(symbol_table);
+    handle_variable_declarations(&input_statement.declarations, &mut new_symbol_table, context);
+    let immutable_symbol_table = Arc::new(new_symbol_table);
+    for statement in &input_statement.statements {
+        handle_statement(statement, immutable_symbol_table.clone(), context);
     }
 }
 
-enum HandleStatementResult {
-    Success,
-    Failure(String),
-}
-
 fn handle_statement(
     statement: &crate::ast::Statement,
     symbol_table: Arc<SymbolTable>,
-) -> HandleStatementResult {
+    context: &mut TypecheckContext,
+) {
     match statement {
         crate::ast::Statement::Expression(expression) => {
-            match handle_expression(&expression, &symbol_table) {
-                HandleExpressionResult::Success(_) => return HandleStatementResult::Success,
-                HandleExpressionResult::Failure(reason) => {
-                    return HandleStatementResult::Failure(reason)
-                }
+            let expression_type = handle_expression(expression, &symbol_table, context);
+            if expression_type == ExpressionType::Array {
+                context.report(Diagnostic::error(
+                    Code::ArrayUsedAsValue,
+                    "an array cannot be used as a value",
+                    expression.span(),
+                ));
             }
         }
-        crate::ast::Statement::ReturnStatement(expression) => match expression {
-            Option::Some(expression) => {
-                return match handle_expression(&expression, &symbol_table) {
-                    HandleExpressionResult::Success(expression_result) => {
-                        if expression_result != ExpressionType::Int {
-                            return HandleStatementResult::Failure(
-                                "Attempt to return a non-Int value".to_string(),
-                            );
-                        }
-                        HandleStatementResult::Success
-                    }
-                    HandleExpressionResult::Failure(reason) => {
-                        HandleStatementResult::Failure(reason)
-                    }
-                }
-            }
-            Option::None => {
-                return HandleStatementResult::Success;
-            }
-        },
+        crate::ast::Statement::ReturnStatement(expression, span) => {
+            handle_return(expression.as_deref(), *span, &symbol_table, context);
+        }
         crate::ast::Statement::WhileStatement(while_statement) => {
-            match handle_expression(&while_statement.condition, &symbol_table) {
-                HandleExpressionResult::Success(expression_type) => {
-                    match handle_statement(&while_statement.statement, symbol_table) {
-                        HandleStatementResult::Success => {
-                            if expression_type != ExpressionType::Int {
-                                return HandleStatementResult::Failure(
-                                    "Use of non-Int in while statement condition".to_string(),
-                                );
-                            }
-                            HandleStatementResult::Success
-                        }
-                        HandleStatementResult::Failure(reason) => {
-                            HandleStatementResult::Failure(reason)
-                        }
-                    }
-                }
-                HandleExpressionResult::Failure(reason) => HandleStatementResult::Failure(reason),
-            }
+            handle_condition(&while_statement.condition, "while", &symbol_table, context);
+            handle_statement(&while_statement.statement, symbol_table, context);
         }
         crate::ast::Statement::IfStatement(if_statement) => match &**if_statement {
-            crate::ast::IfStatement::IfStmt(condition, statement) => {
-                match handle_expression(&condition, &symbol_table) {
-                    HandleExpressionResult::Success(expression_type) => {
-                        match handle_statement(&statement, symbol_table) {
-                            HandleStatementResult::Success => {
-                                if expression_type != ExpressionType::Int {
-                                    return HandleStatementResult::Failure(
-                                        "Use of non-Int in if statement condition".to_string(),
-                                    );
-                                }
-                                HandleStatementResult::Success
-                            }
-                            HandleStatementResult::Failure(reason) => {
-                                HandleStatementResult::Failure(reason)
-                            }
-                        }
-                    }
-                    HandleExpressionResult::Failure(reason) => {
-                        HandleStatementResult::Failure(reason)
-                    }
-                }
+            crate::ast::IfStatement::IfStmt(condition, statement, _) => {
+                handle_condition(condition, "if", &symbol_table, context);
+                handle_statement(statement, symbol_table, context);
             }
-            crate::ast::IfStatement::IfElseStmt(condition, statement, statement2) => {
-                match handle_expression(&condition, &symbol_table) {
-                    HandleExpressionResult::Success(expression_type) => {
-                        match handle_statement(&statement, symbol_table.clone()) {
-                            HandleStatementResult::Success => {
-                                match handle_statement(&statement2, symbol_table) {
-                                    HandleStatementResult::Success => {
-                                        if expression_type != ExpressionType::Int {
-                                            return HandleStatementResult::Failure(
-                                                "Use of non-Int in if statement condition"
-                                                    .to_string(),
-                                            );
-                                        }
-                                        HandleStatementResult::Success
-                                    }
-                                    HandleStatementResult::Failure(reason) => {
-                                        HandleStatementResult::Failure(reason)
-                                    }
-                                }
-                            }
-                            HandleStatementResult::Failure(reason) => {
-                                HandleStatementResult::Failure(reason)
-                            }
-                        }
-                    }
-                    HandleExpressionResult::Failure(reason) => {
-                        HandleStatementResult::Failure(reason)
-                    }
-                }
+            crate::ast::IfStatement::IfElseStmt(condition, statement, statement2, _) => {
+                handle_condition(condition, "if", &symbol_table, context);
+                handle_statement(statement, symbol_table.clone(), context);
+                handle_statement(statement2, symbol_table, context);
             }
         },
-        crate::ast::Statement::EmptyStatement => HandleStatementResult::Success,
+        crate::ast::Statement::EmptyStatement(_) => {}
         crate::ast::Statement::CompoundStatement(compound_statement) => {
-            match handle_compound_statement(&compound_statement, symbol_table) {
-                TypecheckCompoundStatementResult::Success => HandleStatementResult::Success,
-                TypecheckCompoundStatementResult::Failure(reason) => {
-                    HandleStatementResult::Failure(reason)
-                }
-            }
+            handle_compound_statement(compound_statement, symbol_table, context);
         }
     }
 }
 
-enum HandleExpressionResult {
-    Success(ExpressionType),
-    Failure(String),
+fn handle_return(
+    expression: Option<&Expression>,
+    span: Span,
+    symbol_table: &Arc<SymbolTable>,
+    context: &mut TypecheckContext,
+) {
+    let diagnostic = match (expression, context.return_type) {
+        (Some(expression), IdentifierType::Int) => {
+            let expression_type = handle_expression(expression, symbol_table, context);
+            if expression_type.conforms_to(&ExpressionType::Int) {
+                return;
+            }
+            Diagnostic::error(
+                Code::InvalidReturnValue,
+                &format!("cannot return {}", describe(&expression_type)),
+                expression.span(),
+            )
+        }
+        (Some(expression), IdentifierType::Void) => {
+            handle_expression(expression, symbol_table, context);
+            Diagnostic::error(
+                Code::InvalidReturnValue,
+                &format!("cannot return a value from void function `{}`", context.function_name),
+                expression.span(),
+            )
+        }
+        (None, IdentifierType::Int) => Diagnostic::error(
+            Code::InvalidReturnValue,
+            &format!("`return` without a value in function `{}` returning int", context.function_name),
+            span,
+        ),
+        (None, IdentifierType::Void) => return,
+    };
+    let label = format!("`{}` declared here", context.function_name);
+    context.report(diagnostic.with_label(context.function_span, &label));
+}
+
+fn handle_condition(
+    condition: &Expression,
+    statement_name: &str,
+    symbol_table: &Arc<SymbolTable>,
+    context: &mut TypecheckContext,
+) {
+    let expression_type = handle_expression(condition, symbol_table, context);
+    if !expression_type.conforms_to(&ExpressionType::Int) {
+        context.report(Diagnostic::error(
+            Code::NonIntCondition,
+            &format!(
+                "condition of `{}` statement must be an int, found {}",
+                statement_name,
+                describe(&expression_type)
+            ),
+            condition.span(),
+        ));
+    }
 }
 
 fn handle_expression(
     expression: &Expression,
     symbol_table: &Arc<SymbolTable>,
-) -> HandleExpressionResult {
+    context: &mut TypecheckContext,
+) -> ExpressionType {
     match expression {
-        Expression::Assignment(var, expression) => {
-            handle_assignment(&var, &expression, symbol_table)
+        Expression::Assignment(var, expression, span) => {
+            handle_assignment(var, expression, *span, symbol_table, context)
         }
-        Expression::Operation(expression, _, expression2) => {
-            handle_operation(&expression, &expression2, symbol_table)
+        Expression::Operation(expression, operator, expression2, _) => {
+            handle_operation(expression, operator, expression2, symbol_table, context)
         }
-        Expression::Var(var) => match handle_var(&var, symbol_table) {
-            HandleVarResult::Success(expression_type) => {
-                HandleExpressionResult::Success(expression_type)
-            }
-            HandleVarResult::Failure(reason) => HandleExpressionResult::Failure(reason),
-        },
-        Expression::Call(function_call) => handle_function_call(&function_call, symbol_table),
-        Expression::IntegerLiteral(_) => HandleExpressionResult::Success(ExpressionType::Int),
+        Expression::Var(var) => handle_var(var, symbol_table, context),
+        Expression::Call(function_call) => {
+            handle_function_call(function_call, symbol_table, context)
+        }
+        Expression::IntegerLiteral(_, _) => ExpressionType::Int,
     }
 }
 
 fn handle_function_call(
     function_call: &FunctionCall,
     symbol_table: &Arc<SymbolTable>,
-) -> HandleExpressionResult {
-    let function;
-    match symbol_table.symbol_lookup(&function_call.name) {
-        Some(symbol_table_element) => match symbol_table_element {
-            SymbolTableElement::Function(element) => function = element,
-            _ => {
-                return HandleExpressionResult::Failure("Attempt to call non-function".to_string())
-            }
-        },
-        _ => return HandleExpressionResult::Failure("Attempt to call non-function".to_string()),
+    context: &mut TypecheckContext,
+) -> ExpressionType {
+    let function = match symbol_table.symbol_lookup(&function_call.name) {
+        Some(SymbolTableElement::Function(element)) => element,
+        Some(element) => {
+            context.report(
+                Diagnostic::error(
+                    Code::CallOfNonFunction,
+                    &format!("call of non-function `{}`", function_call.name),
+                    function_call.span,
+                )
+                .with_label(element.span(), &format!("`{}` declared here", function_call.name)),
+            );
+            handle_arguments(&function_call.args, symbol_table, context);
+            return ExpressionType::Error;
+        }
+        None => {
+            context.report_undeclared(
+                &function_call.name,
+                Diagnostic::error(
+                    Code::UndeclaredIdentifier,
+                    &format!("call of undeclared function `{}`", function_call.name),
+                    function_call.span,
+                ),
+            );
+            handle_arguments(&function_call.args, symbol_table, context);
+            return ExpressionType::Error;
+        }
     };
-    match function_call {
-        FunctionCall { name: _, args } => {
-            let mut len = 0;
-            for arg in args {
-                match handle_expression(&arg, symbol_table) {
-                    HandleExpressionResult::Failure(reason) => {
-                        return HandleExpressionResult::Failure(reason)
-                    }
-                    HandleExpressionResult::Success(expression_type) => {
-                        match function.argument_types[len] {
-                            Param::Var(_) => {
-                                if expression_type != ExpressionType::Int {
-                                    return HandleExpressionResult::Failure(
-                                        "Attempted to pass non-int, but int was expected"
-                                            .to_string(),
-                                    );
-                                }
-                            }
-                            Param::Arr(_) => {
-                                if expression_type != ExpressionType::Array {
-                                    return HandleExpressionResult::Failure(
-                                        "Attempted to pass int, but array was expected".to_string(),
-                                    );
-                                }
-                            }
-                        }
-                    }
-                }
-                len = len + 1;
-            }
-            if len != function.argument_types.len() {
-                return HandleExpressionResult::Failure(
-                    "Incorrect number of arguments in function call".to_string(),
-                );
-            }
-            match function.return_type {
-                IdentifierType::Int => HandleExpressionResult::Success(ExpressionType::Int),
-                IdentifierType::Void => HandleExpressionResult::Success(ExpressionType::Void),
+    if function_call.args.len() != function.argument_types.len() {
+        let diagnostic = Diagnostic::error(
+            Code::ArgumentCountMismatch,
+            &format!(
+                "`{}` takes {} argument(s) but {} were supplied",
+                function_call.name,
+                function.argument_types.len(),
+                function_call.args.len()
+            ),
+            function_call.span,
+        );
+        // Built-ins cannot be redeclared, so a function with a built-in's name
+        // is the built-in, which has no declaration to point at.
+        let diagnostic = match builtin(&function_call.name) {
+            Some(_) => diagnostic.with_note(&format!("`{}` is a built-in function", function_call.name)),
+            None => diagnostic.with_label(function.span, &format!("`{}` declared here", function_call.name)),
+        };
+        context.report(diagnostic);
+        handle_arguments(&function_call.args, symbol_table, context);
+    } else {
+        for (arg, param) in function_call.args.iter().zip(&function.argument_types) {
+            let expression_type = handle_expression(arg, symbol_table, context);
+            let expected_type = match param {
+                Param::Var => ExpressionType::Int,
+                Param::Arr => ExpressionType::Array,
+            };
+            if !expression_type.conforms_to(&expected_type) {
+                context.report(Diagnostic::error(
+                    Code::ArgumentTypeMismatch,
+                    &format!(
+                        "expected {} argument, found {}",
+                        describe(&expected_type),
+                        describe(&expression_type)
+                    ),
+                    arg.span(),
+                ));
             }
         }
     }
+    match function.return_type {
+        IdentifierType::Int => ExpressionType::Int,
+        IdentifierType::Void => ExpressionType::Void,
+    }
+}
+
+/// Checks the arguments of a call that could not be matched against a
+/// signature, so errors inside them are still reported.
+fn handle_arguments(
+    args: &[Box<Expression>],
+    symbol_table: &Arc<SymbolTable>,
+    context: &mut TypecheckContext,
+) {
+    for arg in args {
+        handle_expression(arg, symbol_table, context);
+    }
 }
 
 fn handle_operation(
     expression1: &Expression,
+    operator: &Operator,
     expression2: &Expression,
     symbol_table: &Arc<SymbolTable>,
-) -> HandleExpressionResult {
-    match handle_expression(expression1, symbol_table) {
-        HandleExpressionResult::Success(expression_type) => {
-            match handle_expression(expression2, symbol_table) {
-                HandleExpressionResult::Success(expression2_type) => {
-                    if expression_type != ExpressionType::Int
-                        || expression2_type != ExpressionType::Int
-                    {
-                        return HandleExpressionResult::Failure(
-                            "Attempt to perform an operation with non-Ints".to_string(),
-                        );
-                    } else {
-                        return HandleExpressionResult::Success(ExpressionType::Int);
-                    }
-                }
-                HandleExpressionResult::Failure(reason) => HandleExpressionResult::Failure(reason),
-            }
+    context: &mut TypecheckContext,
+) -> ExpressionType {
+    for operand in [expression1, expression2] {
+        let expression_type = handle_expression(operand, symbol_table, context);
+        if !expression_type.conforms_to(&ExpressionType::Int) {
+            context.report(Diagnostic::error(
+                Code::NonIntOperand,
+                &format!(
+                    "operand of `{}` must be an int, found {}",
+                    operator.symbol(),
+                    describe(&expression_type)
+                ),
+                operand.span(),
+            ));
         }
-        HandleExpressionResult::Failure(reason) => HandleExpressionResult::Failure(reason),
     }
+    ExpressionType::Int
 }
 
 fn handle_assignment(
     var: &Var,
     expression: &Expression,
+    span: Span,
     symbol_table: &Arc<SymbolTable>,
-) -> HandleExpressionResult {
-    match handle_assignment_left(var, symbol_table) {
-        HandleAssignmentLeftResult::Int => match handle_expression(expression, symbol_table) {
-            HandleExpressionResult::Success(expression_type) => match expression_type {
-                ExpressionType::Int => HandleExpressionResult::Success(ExpressionType::Int),
-                ExpressionType::Array => HandleExpressionResult::Failure(
-                    "Attempt to assign an array to an Int".to_string(),
-                ),
-                _ => HandleExpressionResult::Failure(
-                    "Attempt to assign void to a variable".to_string(),
-                ),
-            },
-            HandleExpressionResult::Failure(reason) => HandleExpressionResult::Failure(reason),
-        },
-        HandleAssignmentLeftResult::Array => match handle_expression(expression, symbol_table) {
-            HandleExpressionResult::Success(expression_type) => match expression_type {
-                ExpressionType::Int => HandleExpressionResult::Failure(
-                    "Attempt to assign an array to an Int".to_string(),
-                ),
-                ExpressionType::Array => HandleExpressionResult::Success(ExpressionType::Array),
-                _ => HandleExpressionResult::Failure(
-                    "Attempt to assign void to a variable".to_string(),
-                ),
-            },
-            HandleExpressionResult::Failure(reason) => HandleExpressionResult::Failure(reason),
-        },
-        HandleAssignmentLeftResult::Failure(reason) => HandleExpressionResult::Failure(reason),
+    context: &mut TypecheckContext,
+) -> ExpressionType {
+    let target_type = handle_assignment_left(var, symbol_table, context);
+    let expression_type = handle_expression(expression, symbol_table, context);
+    if !expression_type.conforms_to(&target_type) {
+        context.report(Diagnostic::error(
+            Code::InvalidAssignment,
+            &format!(
+                "cannot assign {} to {}",
+                describe(&expression_type),
+                describe(&target_type)
+            ),
+            span,
+        ));
     }
-}
-
-enum HandleVarResult {
-    Success(ExpressionType),
-    Failure(String),
-}
-
-enum HandleAssignmentLeftResult {
-    Array,
-    Int,
-    Failure(String),
+    target_type
 }
 
 fn handle_assignment_left(
     var: &Var,
     symbol_table: &Arc<SymbolTable>,
-) -> HandleAssignmentLeftResult {
-    match &var {
-        Var::Var(name) => match symbol_table.symbol_lookup(&name) {
+    context: &mut TypecheckContext,
+) -> ExpressionType {
+    match var {
+        Var::Var(name, span) => match symbol_table.symbol_lookup(name) {
             Some(symbol_table_element) => match symbol_table_element {
-                SymbolTableElement::Variable(_) => HandleAssignmentLeftResult::Int,
-                SymbolTableElement::Array(_) => HandleAssignmentLeftResult::Array,
-                _ => HandleAssignmentLeftResult::Failure(
-                    "Attempted to assign to either a function or array".to_string(),
-                ),
+                SymbolTableElement::Variable(_) => ExpressionType::Int,
+                // Arrays are assigned element by element.
+                _ => {
+                    context.report(with_declaration(
+                        Diagnostic::error(
+                            Code::InvalidAssignment,
+                            &format!("cannot assign to `{}`", name),
+                            *span,
+                        ),
+                        name,
+                        symbol_table_element,
+                    ));
+                    ExpressionType::Error
+                }
             },
             None => {
-                HandleAssignmentLeftResult::Failure("Assignment to undeclared variable".to_string())
+                report_undeclared_variable(name, *span, context);
+                ExpressionType::Error
             }
         },
-        Var::ArrayAccess(name, expression) => {
-            match handle_expression(expression, symbol_table) {
-                HandleExpressionResult::Success(expression_type) => match expression_type {
-                    ExpressionType::Int => {}
-                    _ => {
-                        return HandleAssignmentLeftResult::Failure(
-                            "Attempt to index array by non-Int".to_string(),
-                        )
-                    }
-                },
-                HandleExpressionResult::Failure(reason) => {
-                    return HandleAssignmentLeftResult::Failure(reason)
-                }
-            }
-            match symbol_table.symbol_lookup(&name) {
-                Some(symbol_table_element) => match symbol_table_element {
-                    SymbolTableElement::Array(_) => HandleAssignmentLeftResult::Int,
-                    SymbolTableElement::ParameterArray(_) => HandleAssignmentLeftResult::Int,
-                    _ => HandleAssignmentLeftResult::Failure(
-                        "Attempted to assign to either a function or a variable as an array"
-                            .to_string(),
-                    ),
-                },
-                None => HandleAssignmentLeftResult::Failure(
-                    "Assignment to undeclared array".to_string(),
-                ),
-            }
+        Var::ArrayAccess(name, expression, span) => {
+            handle_array_access(name, expression, *span, symbol_table, context)
         }
     }
 }
 
-fn handle_var(var: &Var, symbol_table: &Arc<SymbolTable>) -> HandleVarResult {
-    match &var {
-        Var::Var(name) => match symbol_table.symbol_lookup(&name) {
+fn handle_var(
+    var: &Var,
+    symbol_table: &Arc<SymbolTable>,
+    context: &mut TypecheckContext,
+) -> ExpressionType {
+    match var {
+        Var::Var(name, span) => match symbol_table.symbol_lookup(name) {
             Some(symbol_table_element) => match symbol_table_element {
-                SymbolTableElement::Function(_) => HandleVarResult::Failure(
-                    "Attempted to use a function as a variable".to_string(),
-                ),
-                SymbolTableElement::Variable(_) => HandleVarResult::Success(ExpressionType::Int),
-                SymbolTableElement::Array(_) => HandleVarResult::Success(ExpressionType::Array),
-                SymbolTableElement::ParameterArray(_) => {
-                    HandleVarResult::Success(ExpressionType::Array)
+                SymbolTableElement::Function(_) => {
+                    context.report(with_declaration(
+                        Diagnostic::error(
+                            Code::FunctionUsedAsVariable,
+                            &format!("function `{}` used as a variable", name),
+                            *span,
+                        ),
+                        name,
+                        symbol_table_element,
+                    ));
+                    ExpressionType::Error
                 }
+                SymbolTableElement::Variable(_) => ExpressionType::Int,
+                SymbolTableElement::Array(_) => ExpressionType::Array,
+                SymbolTableElement::ParameterArray(_) => ExpressionType::Array,
             },
-            None => HandleVarResult::Failure("Assignment to undeclared variable".to_string()),
-        },
-        Var::ArrayAccess(name, expression) => {
-            match handle_expression(expression, symbol_table) {
-                HandleExpressionResult::Success(expression_type) => match expression_type {
-                    ExpressionType::Int => {}
-                    _ => {
-                        return HandleVarResult::Failure(
-                            "Attempt to index array by non-Int".to_string(),
-                        )
-                    }
-                },
-                HandleExpressionResult::Failure(reason) => return HandleVarResult::Failure(reason),
-            }
-            match symbol_table.symbol_lookup(&name) {
-                Some(symbol_table_element) => match symbol_table_element {
-                    SymbolTableElement::Array(_) => HandleVarResult::Success(ExpressionType::Int),
-                    SymbolTableElement::ParameterArray(_) => {
-                        return HandleVarResult::Success(ExpressionType::Int);
-                    }
-                    _ => HandleVarResult::Failure(
-                        "Attempted to use either a function or a variable as an array".to_string(),
-                    ),
-                },
-                None => HandleVarResult::Failure("Assignment to undeclared array".to_string()),
+            None => {
+                report_undeclared_variable(name, *span, context);
+                ExpressionType::Error
             }
+        },
+        Var::ArrayAccess(name, expression, span) => {
+            handle_array_access(name, expression, *span, symbol_table, context)
+        }
+    }
+}
+
+fn handle_array_access(
+    name: &str,
+    index: &Expression,
+    span: Span,
+    symbol_table: &Arc<SymbolTable>,
+    context: &mut TypecheckContext,
+) -> ExpressionType {
+    let index_type = handle_expression(index, symbol_table, context);
+    if !index_type.conforms_to(&ExpressionType::Int) {
+        context.report(Diagnostic::error(
+            Code::NonIntIndex,
+            &format!("array index must be an int, found {}", describe(&index_type)),
+            index.span(),
+        ));
+    }
+    match symbol_table.symbol_lookup(name) {
+        Some(SymbolTableElement::Array(_)) | Some(SymbolTableElement::ParameterArray(_)) => {
+            ExpressionType::Int
+        }
+        Some(symbol_table_element) => {
+            context.report(with_declaration(
+                Diagnostic::error(
+                    Code::NotAnArray,
+                    &format!("`{}` is not an array", name),
+                    span,
+                ),
+                name,
+                symbol_table_element,
+            ));
+            ExpressionType::Error
         }
+        None => {
+            report_undeclared_variable(name, span, context);
+            ExpressionType::Error
+        }
+    }
+}
+
+fn report_undeclared_variable(name: &str, span: Span, context: &mut TypecheckContext) {
+    context.report_undeclared(
+        name,
+        Diagnostic::error(
+            Code::UndeclaredIdentifier,
+            &format!("use of undeclared variable `{}`", name),
+            span,
+        ),
+    );
+}
+
+fn describe(expression_type: &ExpressionType) -> &'static str {
+    match expression_type {
+        ExpressionType::Array => "an array",
+        ExpressionType::Int => "an int",
+        ExpressionType::Void => "a void value",
+        ExpressionType::Error => "an invalid value",
     }
 }